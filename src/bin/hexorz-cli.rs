@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hexorz::core::analysis::Difficulty;
+use hexorz::core::level::Level;
+use hexorz::core::map::*;
+use hexorz::core::notation::*;
+use hexorz::core::replay::*;
+use hexorz::core::rng::Rng;
+
+const USAGE: &str = "usage:
+    hexorz-cli solve <level.hexl | premade:N> [--all N]
+    hexorz-cli render <level.hexl | premade:N> [--svg] [--solution]
+    hexorz-cli verify <replay.hexr>
+    hexorz-cli generate --out <dir> [--count N] [--seed S] [--min-par P]";
+
+// Maps handed out to the worker threads at a time
+const BATCH_SIZE: u64 = 256;
+
+// Maps tried for every level asked for before giving up
+const MAX_TRIES_PER_LEVEL: u64 = 10_000;
+
+// A `.hexl` file, or one of the built-in maps as `premade:0` to `premade:3`
+fn load_map_arg(arg: &str) -> Result<HexMap, String> {
+    if let Some(n) = arg.strip_prefix("premade:") {
+        let arr = match n {
+            "0" => HexMap::PREMADE_MAP_0,
+            "1" => HexMap::PREMADE_MAP_1,
+            "2" => HexMap::PREMADE_MAP_2,
+            "3" => HexMap::PREMADE_MAP_3,
+            _ => return Err(format!("no premade map {}, there are 0 to 3", n)),
+        };
+        return load_map(arr).map_err(|e| format!("{}: {}", arg, e));
+    }
+
+    let src = fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+    let level = src.parse::<Level>().map_err(|e| format!("{}:{}", arg, e))?;
+
+    Ok(level.map)
+}
+
+fn solve(arg: &str, flags: &[&str]) -> Result<(), String> {
+    let map = load_map_arg(arg)?;
+
+    // How many optimal solutions to list, none unless asked
+    let list = match flags {
+        [] => 0,
+        ["--all", n] => n
+            .parse::<usize>()
+            .map_err(|_| format!("--all needs a number, got {}", n))?,
+        [flag, ..] => return Err(format!("unknown option {}", flag)),
+    };
+
+    let solution = map.solve();
+
+    let Some(moves) = solution.moves else {
+        println!("expanded: {}", solution.expanded);
+        return Err(format!("{}: the goal can't be reached", arg));
+    };
+
+    println!("moves: {}", format_moves(&moves));
+    println!("length: {}", moves.len());
+    println!("expanded: {}", solution.expanded);
+    println!("solutions: {}", map.count_solutions());
+
+    for (i, moves) in map.solutions(list).iter().enumerate() {
+        println!("solution {}: {}", i + 1, format_moves(moves));
+    }
+
+    Ok(())
+}
+
+fn render(arg: &str, flags: &[&str]) -> Result<(), String> {
+    let map = load_map_arg(arg)?;
+
+    let mut is_svg = false;
+    let mut with_solution = false;
+    for &flag in flags {
+        match flag {
+            "--svg" => is_svg = true,
+            "--solution" => with_solution = true,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    let moves = if with_solution {
+        let moves = map.solve().moves;
+        Some(moves.ok_or_else(|| format!("{}: the goal can't be reached", arg))?)
+    } else {
+        None
+    };
+
+    if is_svg {
+        print!("{}", map.to_svg(moves.as_deref()));
+    } else {
+        print!("{}", map.to_ascii(moves.as_deref()));
+    }
+
+    Ok(())
+}
+
+fn verify(arg: &str) -> Result<(), String> {
+    let src = fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+    let replay = src
+        .parse::<Replay>()
+        .map_err(|e| format!("{}:{}", arg, e))?;
+    let verification = replay.verify().map_err(|e| format!("{}: {}", arg, e))?;
+
+    let result = match verification.first_bad {
+        _ if verification.is_won => "won",
+        Some((_, _, BadMove::Fell)) => "lost",
+        Some(_) => "illegal",
+        None => "unfinished",
+    };
+    println!("result: {}", result);
+    println!("moves: {}", verification.moves);
+    if let Some((index, dir, bad)) = verification.first_bad {
+        println!("first bad move: {} ({}) {}", index, dir.compass(), bad);
+    }
+
+    if verification.is_won {
+        Ok(())
+    } else {
+        Err(format!("{}: the replay doesn't win", arg))
+    }
+}
+
+struct GenerateOptions {
+    out: String,
+    count: usize,
+    seed: u64,
+    min_par: usize,
+}
+
+fn parse_generate(args: &[&str]) -> Result<GenerateOptions, String> {
+    let mut options = GenerateOptions {
+        out: String::new(),
+        count: 10,
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64),
+        min_par: 0,
+    };
+
+    let mut args = args.iter();
+    while let Some(&flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("{} needs a number, got {}", flag, value))
+        };
+
+        match flag {
+            "--out" => options.out = value.to_string(),
+            "--count" => options.count = number()? as usize,
+            "--seed" => options.seed = number()?,
+            "--min-par" => options.min_par = number()? as usize,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    if options.out.is_empty() {
+        return Err("generate needs --out <dir>".to_owned());
+    }
+
+    Ok(options)
+}
+
+struct Generated {
+    level: Level,
+    difficulty: Difficulty,
+}
+
+// Map number `index` of the run started with `seed`, if it's solvable in at
+// least `min_par` moves. Every map gets its own generator so the results
+// don't depend on which thread made them.
+fn generate_one(seed: u64, index: u64, min_par: usize) -> Option<Generated> {
+    let mut rng = Rng::new(seed ^ Rng::new(index).next_u64());
+    let map = HexMap::gen_with(&mut rng);
+
+    map.validate().ok()?;
+    let difficulty = map.difficulty()?;
+    if difficulty.moves < min_par {
+        return None;
+    }
+
+    let mut level = Level::from_map(map);
+    level.par = Some(difficulty.moves as u32);
+
+    // Make sure the file reads back as the same level
+    let text = level.to_string();
+    if text.parse::<Level>().ok()? != level {
+        return None;
+    }
+
+    Some(Generated { level, difficulty })
+}
+
+fn generate(args: &[&str]) -> Result<(), String> {
+    let options = parse_generate(args)?;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+    let max_tries = MAX_TRIES_PER_LEVEL.saturating_mul(options.count as u64);
+
+    let mut levels = Vec::new();
+    let mut seen = HashSet::new();
+    let mut tried = 0;
+
+    while levels.len() < options.count && tried < max_tries {
+        let batch = tried..tried + BATCH_SIZE;
+        tried = batch.end;
+
+        let found = thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|t| {
+                    let batch = batch.clone();
+                    let options = &options;
+                    scope.spawn(move || {
+                        batch
+                            .skip(t as usize)
+                            .step_by(threads as usize)
+                            .filter_map(|i| {
+                                let generated = generate_one(options.seed, i, options.min_par)?;
+                                Some((i, generated))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut found = workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>();
+            found.sort_by_key(|(i, _)| *i);
+            found
+        });
+
+        for (_, generated) in found {
+            if levels.len() == options.count {
+                break;
+            }
+            let Ok(code) = generated.level.map.canonical_code() else {
+                continue;
+            };
+            if seen.insert(code) {
+                levels.push(generated);
+            }
+        }
+    }
+
+    if levels.len() < options.count {
+        return Err(format!(
+            "only found {} of {} levels in {} tries, try a lower --min-par",
+            levels.len(),
+            options.count,
+            tried
+        ));
+    }
+
+    levels.sort_by(|a, b| a.difficulty.score.total_cmp(&b.difficulty.score));
+
+    let out = Path::new(&options.out);
+    fs::create_dir_all(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+
+    let mut manifest = format!(
+        "name: Generated\n# hexorz-cli generate --count {} --seed {} --min-par {}\n---\n",
+        options.count, options.seed, options.min_par
+    );
+
+    for (i, generated) in levels.iter().enumerate() {
+        let file = format!("{:03}.hexl", i + 1);
+        let path = out.join(&file);
+        fs::write(&path, generated.level.to_string())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        manifest += &format!(
+            "{}  # par {}, {} {:.1}\n",
+            file,
+            generated.difficulty.moves,
+            generated.difficulty.label(),
+            generated.difficulty.score
+        );
+    }
+
+    let path = out.join("manifest");
+    fs::write(&path, manifest).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    println!("seed: {}", options.seed);
+    println!("tried: {}", tried);
+    println!("wrote: {} levels to {}", levels.len(), out.display());
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["solve", level, ref flags @ ..] => solve(level, flags),
+        ["render", level, ref flags @ ..] => render(level, flags),
+        ["verify", replay] => verify(replay),
+        ["generate", ref flags @ ..] => generate(flags),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
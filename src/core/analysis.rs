@@ -0,0 +1,480 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use crate::core::game::PlayerState;
+use crate::core::hex::*;
+use crate::core::map::*;
+
+// The state space of a map: every block position reachable from a start
+// state, and the moves between them that stay on the map.
+pub struct StateGraph {
+    pub states: Vec<PlayerState>,
+    // Moves out of each state and where they lead, by state index
+    pub edges: Vec<Vec<(HexDirection, usize)>>,
+    // Fewest moves from the start state
+    pub depth: Vec<usize>,
+    // State each one was first reached from, giving shortest paths back
+    parent: Vec<Option<usize>>,
+    index: HashMap<PlayerState, usize>,
+}
+
+impl StateGraph {
+    // Breadth first search from `start`, so states come in order of distance
+    pub fn explore(map: &HexMap, start: PlayerState) -> Self {
+        let mut graph = StateGraph {
+            states: vec![start],
+            edges: vec![Vec::new()],
+            depth: vec![0],
+            parent: vec![None],
+            index: HashMap::from([(start, 0)]),
+        };
+
+        let mut queue = VecDeque::from([0]);
+        while let Some(i) = queue.pop_front() {
+            for dir in HEX_DIRECTIONS {
+                let next = graph.states[i].next_state_in_map(dir, map);
+                if next.is_dead() {
+                    continue;
+                }
+
+                let j = match graph.index.get(&next) {
+                    Some(&j) => j,
+                    None => {
+                        let j = graph.states.len();
+                        graph.states.push(next);
+                        graph.edges.push(Vec::new());
+                        graph.depth.push(graph.depth[i] + 1);
+                        graph.parent.push(Some(i));
+                        graph.index.insert(next, j);
+                        queue.push_back(j);
+                        j
+                    }
+                };
+                graph.edges[i].push((dir, j));
+            }
+        }
+
+        graph
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn index_of(&self, state: PlayerState) -> Option<usize> {
+        self.index.get(&state).copied()
+    }
+
+    // Shortest path from the start state to `state`, both included
+    pub fn path_to(&self, state: PlayerState) -> Option<Vec<PlayerState>> {
+        let mut i = self.index_of(state)?;
+        let mut path = vec![self.states[i]];
+
+        while let Some(p) = self.parent[i] {
+            path.push(self.states[p]);
+            i = p;
+        }
+        path.reverse();
+
+        Some(path)
+    }
+
+    // Fewest moves from each state to `target`, `None` where it can't be
+    // reached at all
+    pub fn distances_to(&self, target: usize) -> Vec<Option<usize>> {
+        let mut reverse = vec![Vec::new(); self.len()];
+        for (i, edges) in self.edges.iter().enumerate() {
+            for &(_, j) in edges {
+                reverse[j].push(i);
+            }
+        }
+
+        let mut distance = vec![None; self.len()];
+        distance[target] = Some(0);
+
+        let mut queue = VecDeque::from([target]);
+        while let Some(j) = queue.pop_front() {
+            let d = distance[j].unwrap();
+            for &i in &reverse[j] {
+                if distance[i].is_none() {
+                    distance[i] = Some(d + 1);
+                    queue.push_back(i);
+                }
+            }
+        }
+
+        distance
+    }
+
+    // Number of shortest paths from the start state to `target`. Grows
+    // quickly on open maps, so it stops at `u64::MAX`.
+    pub fn count_paths(&self, target: usize) -> u64 {
+        let mut count = vec![0u64; self.len()];
+        count[0] = 1;
+
+        // States are in order of depth, so every count is final before it
+        // gets passed on
+        for i in 0..self.len() {
+            for &(_, j) in &self.edges[i] {
+                if self.depth[j] == self.depth[i] + 1 {
+                    count[j] = count[j].saturating_add(count[i]);
+                }
+            }
+        }
+
+        count[target]
+    }
+
+    // Up to `limit` shortest paths from the start state to `target`, as the
+    // moves to make. Paths come in the order of `HEX_DIRECTIONS`.
+    pub fn paths(&self, target: usize, limit: usize) -> Vec<Vec<HexDirection>> {
+        let distance = self.distances_to(target);
+        let mut paths = Vec::new();
+        let mut moves = Vec::new();
+        self.collect_paths(0, &distance, limit, &mut moves, &mut paths);
+        paths
+    }
+
+    fn collect_paths(
+        &self,
+        i: usize,
+        distance: &[Option<usize>],
+        limit: usize,
+        moves: &mut Vec<HexDirection>,
+        paths: &mut Vec<Vec<HexDirection>>,
+    ) {
+        if paths.len() >= limit {
+            return;
+        }
+        let Some(d) = distance[i] else {
+            return;
+        };
+        if d == 0 {
+            paths.push(moves.clone());
+            return;
+        }
+
+        for &(dir, j) in &self.edges[i] {
+            if distance[j] == Some(d - 1) {
+                moves.push(dir);
+                self.collect_paths(j, distance, limit, moves, paths);
+                moves.pop();
+            }
+        }
+    }
+}
+
+// Weights of the parts of `Difficulty::score`
+const WEIGHT_MOVES: f32 = 1.0;
+const WEIGHT_ORIENTATION: f32 = 0.5;
+const WEIGHT_BRANCHING: f32 = 2.0;
+// Per share of dead-end states, so big maps aren't hard just for being big
+const WEIGHT_DEAD_ENDS: f32 = 20.0;
+const WEIGHT_DETOUR: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Difficulty {
+    // Moves in the optimal solution
+    pub moves: usize,
+    // Times the block stands up or lies down on the optimal solution, the
+    // rest of the moves are sideways rolls
+    pub orientation_changes: usize,
+    // Moves that stay on the map, on average over the reachable states
+    pub branching: f32,
+    // Distinct optimal solutions, one forced line is better than many
+    // equivalent routes
+    pub solutions: u64,
+    // Reachable states with a single move out, which only leads back
+    pub dead_ends: usize,
+    pub states: usize,
+    // Optimal moves against rolling straight from start to goal
+    pub detour: f32,
+    pub score: f32,
+}
+
+impl Difficulty {
+    pub fn label(&self) -> &'static str {
+        match self.score {
+            s if s < 20.0 => "easy",
+            s if s < 30.0 => "medium",
+            s if s < 40.0 => "hard",
+            _ => "expert",
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.1}: {} moves, {} optimal solutions, {} orientation changes, branching {:.2}, {}/{} dead ends, detour {:.2}",
+            self.label(),
+            self.score,
+            self.moves,
+            self.solutions,
+            self.orientation_changes,
+            self.branching,
+            self.dead_ends,
+            self.states,
+            self.detour
+        )
+    }
+}
+
+impl HexMap {
+    // The state graph from the level's start, which the analyses below can
+    // share instead of each exploring the map again
+    pub fn state_graph(&self) -> StateGraph {
+        StateGraph::explore(self, PlayerState::Standing(self.start))
+    }
+
+    // Fewest moves from the start to the goal, or why the map can't be
+    // played. Same checks as `validate`.
+    pub fn moves_in(&self, graph: &StateGraph) -> Result<usize, MapError> {
+        if !self.is_in_map(self.start) {
+            return Err(MapError::MissingStart);
+        }
+        if !self.is_in_map(self.goal) {
+            return Err(MapError::MissingGoal);
+        }
+
+        graph
+            .index_of(PlayerState::Standing(self.goal))
+            .map(|goal| graph.depth[goal])
+            .ok_or(MapError::UnreachableGoal {
+                start: self.start,
+                goal: self.goal,
+            })
+    }
+
+    // `None` if the goal can't be reached from the start
+    pub fn difficulty(&self) -> Option<Difficulty> {
+        self.difficulty_in(&self.state_graph())
+    }
+
+    pub fn difficulty_in(&self, graph: &StateGraph) -> Option<Difficulty> {
+        let goal = graph.index_of(PlayerState::Standing(self.goal))?;
+        let path = graph.path_to(graph.states[goal])?;
+
+        let moves = path.len() - 1;
+        let orientation_changes = path
+            .windows(2)
+            .filter(|step| {
+                matches!(step[0], PlayerState::Standing(_))
+                    != matches!(step[1], PlayerState::Standing(_))
+            })
+            .count();
+
+        let total_edges = graph.edges.iter().map(Vec::len).sum::<usize>();
+        let branching = total_edges as f32 / graph.len() as f32;
+
+        // Every move can be rolled back, so no state is stuck for good, but
+        // these send the player back the way they came
+        let dead_ends = graph.edges.iter().filter(|edges| edges.len() == 1).count();
+        let dead_end_share = dead_ends as f32 / graph.len() as f32;
+
+        // Rolling in a straight line covers 3 hexes every 2 moves
+        let straight = ((2 * self.start.distance(self.goal) + 2) / 3).max(1);
+        let detour = moves as f32 / straight as f32;
+
+        let score = WEIGHT_MOVES * moves as f32
+            + WEIGHT_ORIENTATION * orientation_changes as f32
+            + WEIGHT_BRANCHING * branching
+            + WEIGHT_DEAD_ENDS * dead_end_share
+            + WEIGHT_DETOUR * (detour - 1.0).max(0.0);
+
+        Some(Difficulty {
+            moves,
+            solutions: graph.count_paths(goal),
+            orientation_changes,
+            branching,
+            dead_ends,
+            states: graph.len(),
+            detour,
+            score,
+        })
+    }
+
+    // Number of distinct optimal solutions, 0 if there are none
+    pub fn count_solutions(&self) -> u64 {
+        let graph = StateGraph::explore(self, PlayerState::Standing(self.start));
+        graph
+            .index_of(PlayerState::Standing(self.goal))
+            .map_or(0, |goal| graph.count_paths(goal))
+    }
+
+    // Up to `limit` optimal solutions, as the moves to make
+    pub fn solutions(&self, limit: usize) -> Vec<Vec<HexDirection>> {
+        let graph = StateGraph::explore(self, PlayerState::Standing(self.start));
+        graph
+            .index_of(PlayerState::Standing(self.goal))
+            .map_or_else(Vec::new, |goal| graph.paths(goal, limit))
+    }
+
+    // Every state the block can be in on this map without falling
+    fn all_states(&self) -> Vec<PlayerState> {
+        let mut states = Vec::new();
+
+        for hex in self.hexmap.iter() {
+            states.push(PlayerState::Standing(hex));
+
+            for dir in HEX_DIRECTIONS {
+                let tail = hex.neighbor(dir);
+                if self.is_in_map(tail) {
+                    states.push(PlayerState::Flat(hex, tail));
+                }
+            }
+        }
+
+        states
+    }
+
+    // States the block can get to from the level's start
+    pub fn reachable_states(&self) -> HashSet<PlayerState> {
+        StateGraph::explore(self, PlayerState::Standing(self.start))
+            .states
+            .into_iter()
+            .collect()
+    }
+
+    // States the goal can be reached from, wherever the block starts
+    pub fn winning_states(&self) -> HashSet<PlayerState> {
+        if !self.is_in_map(self.goal) {
+            return HashSet::new();
+        }
+
+        let mut reverse: HashMap<PlayerState, Vec<PlayerState>> = HashMap::new();
+        for state in self.all_states() {
+            for dir in HEX_DIRECTIONS {
+                let next = state.next_state_in_map(dir, self);
+                if !next.is_dead() {
+                    reverse.entry(next).or_default().push(state);
+                }
+            }
+        }
+
+        let goal = PlayerState::Standing(self.goal);
+        let mut winning = HashSet::from([goal]);
+        let mut queue = VecDeque::from([goal]);
+
+        while let Some(state) = queue.pop_front() {
+            for &prev in reverse.get(&state).into_iter().flatten() {
+                if winning.insert(prev) {
+                    queue.push_back(prev);
+                }
+            }
+        }
+
+        winning
+    }
+
+    // Tiles the block never touches when starting from the level's start
+    pub fn unused_tiles(&self) -> Vec<Hex> {
+        self.unused_tiles_in(&self.state_graph())
+    }
+
+    pub fn unused_tiles_in(&self, graph: &StateGraph) -> Vec<Hex> {
+        let used = graph
+            .states
+            .iter()
+            .flat_map(PlayerState::hexes)
+            .collect::<HashSet<_>>();
+
+        self.hexmap
+            .iter()
+            .filter(|hex| !used.contains(hex))
+            .collect()
+    }
+
+    // Reachable states the goal can't be reached from anymore, nearest to
+    // the start first
+    pub fn stuck_states(&self) -> Vec<PlayerState> {
+        self.stuck_states_in(&self.state_graph())
+    }
+
+    // A path to the goal only goes through reachable states, so the graph
+    // from the start is enough to tell
+    pub fn stuck_states_in(&self, graph: &StateGraph) -> Vec<PlayerState> {
+        let distance = match graph.index_of(PlayerState::Standing(self.goal)) {
+            Some(goal) => graph.distances_to(goal),
+            None => vec![None; graph.len()],
+        };
+
+        graph
+            .states
+            .iter()
+            .zip(distance)
+            .filter(|(_, d)| d.is_none())
+            .map(|(state, _)| *state)
+            .collect()
+    }
+
+    // Other tiles the level could start on and still be solvable
+    pub fn alternative_starts(&self) -> Vec<Hex> {
+        let winning = self.winning_states();
+
+        self.hexmap
+            .iter()
+            .filter(|&hex| hex != self.start && hex != self.goal)
+            .filter(|&hex| winning.contains(&PlayerState::Standing(hex)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_graph_matches_separate_searches() {
+        for map in premade_maps() {
+            let graph = map.state_graph();
+
+            let moves = map.solve_path(map.start).unwrap().len() - 1;
+            assert_eq!(map.moves_in(&graph), Ok(moves));
+
+            let winning = map.winning_states();
+            let stuck = map
+                .reachable_states()
+                .into_iter()
+                .filter(|state| !winning.contains(state))
+                .collect::<HashSet<_>>();
+            assert_eq!(
+                map.stuck_states_in(&graph)
+                    .into_iter()
+                    .collect::<HashSet<_>>(),
+                stuck
+            );
+        }
+    }
+
+    #[test]
+    fn listed_solutions_are_all_the_optimal_ones() {
+        for map in premade_maps() {
+            let moves = map.difficulty().unwrap().moves;
+            let solutions = map.solutions(usize::MAX);
+            assert_eq!(solutions.len() as u64, map.count_solutions());
+
+            for solution in solutions {
+                assert_eq!(solution.len(), moves);
+                let end = solution
+                    .iter()
+                    .fold(PlayerState::Standing(map.start), |state, dir| {
+                        state.next_state_in_map(*dir, &map)
+                    });
+                assert_eq!(end, PlayerState::Standing(map.goal));
+            }
+        }
+    }
+
+    #[test]
+    fn moves_in_reports_unplayable_maps() {
+        let mut map = premade_maps().remove(0);
+        map.hexmap.remove(map.goal);
+        assert_eq!(map.moves_in(&map.state_graph()), Err(MapError::MissingGoal));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::core::code::CodeError;
+use crate::core::grid::*;
+use crate::core::hex::*;
+use crate::core::map::*;
+
+// Two maps are the same puzzle if one is a translated, rotated or mirrored
+// copy of the other. The canonical form picks one fixed representative out
+// of all of those, so comparing canonical forms finds duplicates.
+
+type MapKey = (Vec<(i32, i32)>, (i32, i32), (i32, i32));
+
+impl HexMap {
+    // Applies `f` to every hex, then moves the map so its axial bounding box
+    // starts at (0, 0). Turning a long diagonal map can grow its bounding
+    // box past `MAX_CELLS`.
+    fn transformed(&self, f: impl Fn(Hex) -> Hex) -> Result<HexMap, GridTooLarge> {
+        let hexes = self.hexmap.iter().map(&f).collect::<Vec<_>>();
+
+        let min_q = hexes.iter().map(|hex| hex.q).min().unwrap_or(0);
+        let min_r = hexes.iter().map(|hex| hex.r).min().unwrap_or(0);
+        let offset = Hex::from_axial(min_q, min_r);
+
+        Ok(HexMap {
+            hexmap: HexGrid::try_from_iter(hexes.into_iter().map(|hex| hex - offset))?,
+            start: f(self.start) - offset,
+            goal: f(self.goal) - offset,
+        })
+    }
+
+    fn key(&self) -> MapKey {
+        // Already sorted, the grid goes row by row
+        let tiles = self
+            .hexmap
+            .iter()
+            .map(|hex| (hex.r, hex.q))
+            .collect::<Vec<_>>();
+
+        (
+            tiles,
+            (self.start.r, self.start.q),
+            (self.goal.r, self.goal.q),
+        )
+    }
+
+    // The smallest of the 12 rotations and reflections, tiles compared row
+    // by row, then start, then goal
+    pub fn canonical(&self) -> Result<HexMap, GridTooLarge> {
+        let origin = Hex::from_axial(0, 0);
+
+        let mut best: Option<(MapKey, HexMap)> = None;
+
+        for mirror in [false, true] {
+            for steps in 0..6 {
+                let map = self.transformed(|hex| {
+                    let hex = if mirror {
+                        hex.reflect(origin, HexAxis::Q)
+                    } else {
+                        hex
+                    };
+                    hex.rotate(origin, steps)
+                })?;
+                let key = map.key();
+
+                if best.as_ref().is_none_or(|(best_key, _)| key < *best_key) {
+                    best = Some((key, map));
+                }
+            }
+        }
+
+        Ok(best.unwrap().1)
+    }
+
+    // Level code of the canonical form, equal for maps that are the same
+    // puzzle
+    pub fn canonical_code(&self) -> Result<String, CodeError> {
+        let canonical = self.canonical().map_err(|_| CodeError::TooLarge)?;
+        canonical.to_code()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turned_and_mirrored_copies_have_one_canonical_form() {
+        let center = Hex::from_axial(-7, 3);
+
+        for map in premade_maps() {
+            let canonical = map.canonical().unwrap();
+            let code = map.canonical_code().unwrap();
+
+            for mirror in [false, true] {
+                for steps in 0..6 {
+                    let copy = map
+                        .transformed(|hex| {
+                            let hex = if mirror {
+                                hex.reflect(center, HexAxis::R)
+                            } else {
+                                hex
+                            };
+                            hex.rotate(center, steps) + Hex::from_axial(5, -9)
+                        })
+                        .unwrap();
+
+                    assert_eq!(copy.canonical().unwrap(), canonical);
+                    assert_eq!(copy.canonical_code().unwrap(), code);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_form_roundtrips() {
+        for map in premade_maps() {
+            let canonical = map.canonical().unwrap();
+
+            assert_eq!(canonical.canonical().unwrap(), canonical);
+            assert_eq!(
+                HexMap::from_code(&map.canonical_code().unwrap()).unwrap(),
+                canonical
+            );
+
+            // Still the same puzzle
+            assert_eq!(
+                canonical.solve().moves.map(|moves| moves.len()),
+                map.solve().moves.map(|moves| moves.len())
+            );
+        }
+    }
+
+    #[test]
+    fn different_maps_stay_different() {
+        let codes = premade_maps()
+            .iter()
+            .map(|map| map.canonical_code().unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(codes.len(), 4);
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::core::grid::*;
+use crate::core::hex::*;
+use crate::core::map::*;
+
+// Level codes are a compact, URL-safe form of a map for sharing in chat.
+//
+//     version: u8
+//     width, height: varint         axial bounding box, q by r
+//     start, goal: varint           cell index, r * width + q
+//     tiles: width * height bits    row by row, least significant bit first
+//
+// The whole thing is base64 encoded without padding. Codes don't keep the
+// map's position, decoded maps have their bounding box at (0, 0).
+
+const CODE_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeError {
+    InvalidBase64,
+    UnsupportedVersion(u8),
+    Truncated,
+    TooLarge,
+    InvalidMap(MapError),
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodeError::InvalidBase64 => write!(f, "level code is not valid base64"),
+            CodeError::UnsupportedVersion(v) => write!(f, "unsupported level code version {}", v),
+            CodeError::Truncated => write!(f, "level code is truncated"),
+            CodeError::TooLarge => write!(f, "level code describes a map that is too large"),
+            CodeError::InvalidMap(e) => write!(f, "level code is invalid: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodeError {}
+
+fn write_varint(bytes: &mut Vec<u8>, mut n: u32) {
+    while n >= 0x80 {
+        bytes.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Result<u32, CodeError> {
+    let mut n = 0u32;
+
+    for shift in (0..32).step_by(7) {
+        let byte = bytes.next().ok_or(CodeError::Truncated)?;
+        n |= ((byte & 0x7f) as u32) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+
+    Err(CodeError::TooLarge)
+}
+
+impl HexMap {
+    // Fails if the map is spread over more than `MAX_CELLS`
+    pub fn to_code(&self) -> Result<String, CodeError> {
+        // Start and goal count too, the editor can leave them off the tiles
+        let hexes = (self.hexmap.bounds().into_iter())
+            .flat_map(|(min, max)| [min, max])
+            .chain([self.start, self.goal])
+            .collect::<Vec<_>>();
+        let min_q = hexes.iter().map(|hex| hex.q).min().unwrap();
+        let max_q = hexes.iter().map(|hex| hex.q).max().unwrap();
+        let min_r = hexes.iter().map(|hex| hex.r).min().unwrap();
+        let max_r = hexes.iter().map(|hex| hex.r).max().unwrap();
+
+        let width = max_q as i64 - min_q as i64 + 1;
+        let height = max_r as i64 - min_r as i64 + 1;
+        let cells = width.checked_mul(height);
+        if cells.is_none_or(|cells| cells > MAX_CELLS as i64) {
+            return Err(CodeError::TooLarge);
+        }
+
+        let (width, height) = (width as u32, height as u32);
+        let index = |hex: Hex| (hex.r - min_r) as u32 * width + (hex.q - min_q) as u32;
+
+        let mut bytes = vec![CODE_VERSION];
+        write_varint(&mut bytes, width);
+        write_varint(&mut bytes, height);
+        write_varint(&mut bytes, index(self.start));
+        write_varint(&mut bytes, index(self.goal));
+
+        let mut tiles = vec![0u8; (width * height).div_ceil(8) as usize];
+        for hex in self.hexmap.iter() {
+            let i = index(hex);
+            tiles[i as usize / 8] |= 1 << (i % 8);
+        }
+        bytes.extend(tiles);
+
+        Ok(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn from_code(code: &str) -> Result<Self, CodeError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(code.trim())
+            .map_err(|_| CodeError::InvalidBase64)?;
+        let mut bytes = bytes.into_iter();
+
+        let version = bytes.next().ok_or(CodeError::Truncated)?;
+        if version != CODE_VERSION {
+            return Err(CodeError::UnsupportedVersion(version));
+        }
+
+        let width = read_varint(&mut bytes)?;
+        let height = read_varint(&mut bytes)?;
+        let cells = width.checked_mul(height).ok_or(CodeError::TooLarge)?;
+        // Same cap as the grid, so every map that loads has a code
+        if cells as usize > MAX_CELLS {
+            return Err(CodeError::TooLarge);
+        }
+        if cells == 0 {
+            return Err(CodeError::InvalidMap(MapError::MissingStart));
+        }
+
+        let hex_at = |i: u32| Hex::from_axial((i % width) as i32, (i / width) as i32);
+        let start = read_varint(&mut bytes)?;
+        let goal = read_varint(&mut bytes)?;
+
+        let tiles = bytes.collect::<Vec<_>>();
+        if tiles.len() < cells.div_ceil(8) as usize {
+            return Err(CodeError::Truncated);
+        }
+
+        let mut hexmap = HexGrid::new();
+        for i in 0..cells {
+            if tiles[i as usize / 8] & (1 << (i % 8)) != 0 {
+                hexmap.insert(hex_at(i)).map_err(|_| CodeError::TooLarge)?;
+            }
+        }
+
+        let map = HexMap {
+            hexmap,
+            start: hex_at(start),
+            goal: hex_at(goal),
+        };
+        map.validate().map_err(CodeError::InvalidMap)?;
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same map with its bounding box moved to (0, 0), which is what
+    // decoding gives back
+    fn at_origin(map: &HexMap) -> HexMap {
+        let (min, _) = map.hexmap.bounds().unwrap();
+        let offset = Hex::from_axial(min.q, min.r);
+
+        HexMap {
+            hexmap: HexGrid::try_from_iter(map.hexmap.iter().map(|hex| hex - offset)).unwrap(),
+            start: map.start - offset,
+            goal: map.goal - offset,
+        }
+    }
+
+    #[test]
+    fn code_roundtrip() {
+        for map in premade_maps() {
+            let code = map.to_code().unwrap();
+            let decoded = HexMap::from_code(&code).unwrap();
+
+            assert_eq!(decoded, at_origin(&map));
+            assert_eq!(decoded.to_code().unwrap(), code);
+            assert_eq!(HexMap::from_code(&format!("  {}\n", code)), Ok(decoded));
+        }
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for n in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, n);
+            assert_eq!(read_varint(&mut bytes.into_iter()), Ok(n));
+        }
+    }
+
+    #[test]
+    fn bad_codes() {
+        let code = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+
+        assert_eq!(
+            HexMap::from_code("not a code!"),
+            Err(CodeError::InvalidBase64)
+        );
+        assert_eq!(HexMap::from_code(""), Err(CodeError::Truncated));
+        assert_eq!(
+            HexMap::from_code(&code(&[2])),
+            Err(CodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            HexMap::from_code(&code(&[CODE_VERSION, 3])),
+            Err(CodeError::Truncated)
+        );
+        assert_eq!(
+            HexMap::from_code(&code(&[CODE_VERSION, 0xff, 0xff, 0x04, 0xff, 0xff, 0x04])),
+            Err(CodeError::TooLarge)
+        );
+        assert_eq!(
+            HexMap::from_code(&code(&[CODE_VERSION, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01])),
+            Err(CodeError::TooLarge)
+        );
+
+        // A whole map's worth of tiles but missing the last byte
+        let full = URL_SAFE_NO_PAD
+            .decode(load_map(HexMap::PREMADE_MAP_3).unwrap().to_code().unwrap())
+            .unwrap();
+        assert_eq!(
+            HexMap::from_code(&code(&full[..full.len() - 1])),
+            Err(CodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn unplayable_codes_are_rejected() {
+        let mut map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        map.hexmap.remove(map.goal);
+
+        assert_eq!(
+            HexMap::from_code(&map.to_code().unwrap()),
+            Err(CodeError::InvalidMap(MapError::MissingGoal))
+        );
+    }
+
+    #[test]
+    fn start_and_goal_off_the_tiles_still_encode() {
+        for map in premade_maps() {
+            let mut no_start = map.clone();
+            no_start.hexmap.remove(map.start);
+            assert_eq!(
+                HexMap::from_code(&no_start.to_code().unwrap()),
+                Err(CodeError::InvalidMap(MapError::MissingStart))
+            );
+
+            // Far away, past the tiles' bounding box
+            let mut far_goal = map.clone();
+            far_goal.goal = Hex::from_axial(-40, 30);
+            assert_eq!(
+                HexMap::from_code(&far_goal.to_code().unwrap()),
+                Err(CodeError::InvalidMap(MapError::MissingGoal))
+            );
+
+            let mut too_far = map.clone();
+            too_far.goal = Hex::from_axial(1 << 20, 1 << 20);
+            assert_eq!(too_far.to_code(), Err(CodeError::TooLarge));
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use macroquad::prelude::*;
+
+use crate::core::analysis::Difficulty;
+use crate::core::game::*;
+use crate::core::grid::*;
+use crate::core::hex::*;
+use crate::core::level::*;
+use crate::core::map::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub const EDITOR_FILE: &str = "level.hexl";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tool {
+    Floor,
+    Start,
+    Goal,
+    Line,
+}
+
+pub struct Editor {
+    pub level: Level,
+    pub tool: Tool,
+    // Optimal number of moves, or why the map can't be played
+    pub status: Result<usize, MapError>,
+    pub difficulty: Option<Difficulty>,
+    // Tiles the block can never get to from the start
+    pub unused: Vec<Hex>,
+    // Tiles the block can't get to the goal from
+    pub stuck: Vec<Hex>,
+    // Other tiles the level would still be solvable from
+    pub alternative_starts: usize,
+    pub is_playtest: bool,
+    hover: Option<Hex>,
+    // First end of a line being drawn with `Tool::Line`
+    anchor: Option<Hex>,
+}
+
+impl Editor {
+    pub fn new(level: Level) -> Self {
+        let mut editor = Editor {
+            level,
+            tool: Tool::Floor,
+            status: Ok(0),
+            difficulty: None,
+            unused: Vec::new(),
+            stuck: Vec::new(),
+            alternative_starts: 0,
+            is_playtest: false,
+            hover: None,
+            anchor: None,
+        };
+        editor.refresh();
+        editor
+    }
+
+    // Shows the edited map on the board, keeping the view still while
+    // tiles come and go
+    pub fn enter(&mut self, game: &mut Game) {
+        self.is_playtest = false;
+        game.update_map(self.level.map.clone());
+        game.fixed_center = Some(game.get_center());
+    }
+
+    pub fn leave(&mut self, game: &mut Game) {
+        game.fixed_center = None;
+        self.is_playtest = false;
+    }
+
+    fn refresh(&mut self) {
+        let map = &self.level.map;
+        let graph = map.state_graph();
+
+        self.status = map.moves_in(&graph);
+        self.difficulty = map.difficulty_in(&graph);
+        self.unused = map.unused_tiles_in(&graph);
+        self.stuck = map
+            .stuck_states_in(&graph)
+            .iter()
+            .flat_map(PlayerState::hexes)
+            .collect();
+        // Needs a search backwards from the goal, and is only shown for
+        // playable maps
+        self.alternative_starts = match self.status {
+            Ok(_) => map.alternative_starts().len(),
+            Err(_) => 0,
+        };
+    }
+
+    // Returns whether the map may have changed. Tiles that would spread the
+    // map over more than `MAX_CELLS` are left out.
+    fn apply(&mut self, hex: Hex, erase: bool) -> bool {
+        self.try_apply(hex, erase).unwrap_or(true)
+    }
+
+    fn try_apply(&mut self, hex: Hex, erase: bool) -> Result<bool, GridTooLarge> {
+        let map = &mut self.level.map;
+
+        if erase {
+            return Ok(map.hexmap.remove(hex));
+        }
+
+        match self.tool {
+            Tool::Floor => {
+                if !map.hexmap.remove(hex) {
+                    map.hexmap.insert(hex)?;
+                }
+            }
+            Tool::Start => {
+                map.hexmap.insert(hex)?;
+                map.start = hex;
+            }
+            Tool::Goal => {
+                map.hexmap.insert(hex)?;
+                map.goal = hex;
+            }
+            Tool::Line => match self.anchor.take() {
+                Some(anchor) => {
+                    for hex in anchor.line_to(hex) {
+                        map.hexmap.insert(hex)?;
+                    }
+                }
+                None => {
+                    self.anchor = Some(hex);
+                    return Ok(false);
+                }
+            },
+        }
+
+        Ok(true)
+    }
+
+    // Rotates or mirrors the whole map around the start
+    fn transform(&mut self, f: impl Fn(Hex, Hex) -> Hex) {
+        let map = &mut self.level.map;
+        let center = map.start;
+
+        // A turned map can span more cells, it stays as it was if too many
+        let Ok(hexmap) = HexGrid::try_from_iter(map.hexmap.iter().map(|hex| f(hex, center))) else {
+            return;
+        };
+        map.hexmap = hexmap;
+        map.start = f(map.start, center);
+        map.goal = f(map.goal, center);
+
+        for (from, to) in &mut self.level.links {
+            *from = f(*from, center);
+            *to = f(*to, center);
+        }
+    }
+
+    pub fn update(&mut self, game: &mut Game) {
+        if is_key_pressed(KeyCode::Key1) {
+            self.tool = Tool::Floor;
+        }
+        if is_key_pressed(KeyCode::Key2) {
+            self.tool = Tool::Start;
+        }
+        if is_key_pressed(KeyCode::Key3) {
+            self.tool = Tool::Goal;
+        }
+        if is_key_pressed(KeyCode::Key4) {
+            self.tool = Tool::Line;
+        }
+        if self.tool != Tool::Line {
+            self.anchor = None;
+        }
+
+        let mut changed = false;
+        if is_key_pressed(KeyCode::R) {
+            self.transform(|hex, center| hex.rotate(center, 1));
+            changed = true;
+        }
+        if is_key_pressed(KeyCode::M) {
+            self.transform(|hex, center| hex.reflect(center, HexAxis::Q));
+            changed = true;
+        }
+
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 {
+            game.zoom(wheel.signum() * 0.5);
+        }
+
+        let hex = game.screen_to_hex(mouse_position().into());
+        self.hover = Some(hex);
+
+        let left = is_mouse_button_pressed(MouseButton::Left);
+        let right = is_mouse_button_pressed(MouseButton::Right);
+
+        if (left || right) && self.apply(hex, right) {
+            changed = true;
+        }
+
+        if changed {
+            self.refresh();
+            game.update_map(self.level.map.clone());
+        }
+    }
+
+    // Saves to `EDITOR_FILE`, or to the clipboard where there is no file
+    // system. Returns a message for the player.
+    pub fn save(&mut self) -> String {
+        if let Ok(moves) = self.status {
+            self.level.par = Some(moves as u32);
+        }
+
+        let text = self.level.to_string();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match std::fs::write(EDITOR_FILE, text) {
+                Ok(()) => format!("Saved to {}", EDITOR_FILE),
+                Err(e) => format!("Failed to save {}: {}", EDITOR_FILE, e),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            miniquad::window::clipboard_set(&text);
+            "Level copied to clipboard".to_owned()
+        }
+    }
+
+    // Draws on top of `Game::draw`, with its camera still set
+    pub fn draw(&self, game: &Game) {
+        for &hex in &self.unused {
+            game.draw_hex_filled(hex, Color::new(0.0, 0.0, 0.0, 0.5));
+        }
+        for &hex in &self.stuck {
+            game.draw_hex_filled(hex, Color::new(1.0, 0.0, 0.0, 0.3));
+        }
+
+        let goal = game.hex_to_world(self.level.map.goal) + TILE_CENTER;
+        draw_circle_lines(goal.x, goal.y, 6.0, 2.0, RED);
+
+        if let Some(anchor) = self.anchor {
+            for hex in anchor.line_to(self.hover.unwrap_or(anchor)) {
+                game.draw_hex_outline(hex, GREEN);
+            }
+        }
+
+        if let Some(hex) = self.hover {
+            game.draw_hex_outline(hex, YELLOW);
+        }
+
+        set_default_camera();
+
+        let tool = match self.tool {
+            Tool::Floor => "floor",
+            Tool::Start => "start",
+            Tool::Goal => "goal",
+            Tool::Line => "line",
+        };
+        let text = format!("Tool: {}", tool);
+        draw_text(&text, 10.0, 40.0, 40.0, GREEN);
+
+        let (status, color) = match &self.status {
+            Ok(moves) => match self.difficulty {
+                Some(difficulty) => (
+                    format!(
+                        "Optimal solution: {} moves, {} of them, {} ({:.1})",
+                        moves,
+                        difficulty.solutions,
+                        difficulty.label(),
+                        difficulty.score
+                    ),
+                    WHITE,
+                ),
+                None => (format!("Optimal solution: {} moves", moves), WHITE),
+            },
+            Err(e) => (e.to_string(), RED),
+        };
+        draw_text(&status, 10.0, 80.0, 30.0, color);
+
+        if self.status.is_ok() {
+            let text = format!(
+                "{} unused tiles, {} other starts would work",
+                self.unused.len(),
+                self.alternative_starts
+            );
+            draw_text(&text, 10.0, 110.0, 24.0, GRAY);
+        }
+
+        draw_text(
+            "1 floor, 2 start, 3 goal, 4 line, RMB erase, R rotate, M mirror, TAB playtest, S save, ESC exit",
+            10.0,
+            screen_height() - 20.0,
+            24.0,
+            GRAY,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transform_moves_links_with_the_tiles() {
+        let map = load_map(HexMap::PREMADE_MAP_1).unwrap();
+        let mut level = Level::from_map(map);
+        let (from, to) = (level.map.start + DIR[0], level.map.goal + DIR[3]);
+        level.links = vec![(from, to)];
+
+        let mut editor = Editor::new(level);
+        let center = editor.level.map.start;
+        editor.transform(|hex, center| hex.rotate(center, 1));
+
+        assert_eq!(
+            editor.level.links,
+            vec![(from.rotate(center, 1), to.rotate(center, 1))]
+        );
+    }
+}
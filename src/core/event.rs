@@ -0,0 +1,23 @@
+use crate::core::game::{Fall, PlayerState};
+use crate::core::hex::*;
+
+// Things that happen in the game core, for audio, effects, stats and the
+// state machine in `main.rs` to react to. `Game` queues them up and
+// `Game::drain_events` hands them out once.
+//
+// There are no breakable tiles or switches yet, their events come with them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    // A move was made, whether the block stayed on the map or not
+    Moved(HexDirection),
+    // The block tipped over or slid onto new tiles
+    Rolled { from: PlayerState, to: PlayerState },
+    // The block went over the edge
+    Fell { from: PlayerState, fall: Fall },
+    // The block stands on the goal
+    Won,
+    // The last move was taken back, and dropped from the replay
+    Undone { from: PlayerState, to: PlayerState },
+    // A map was put on the board, or the same one started over
+    LevelLoaded,
+}
@@ -1,20 +1,90 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
 
+#[cfg(feature = "audio")]
 use macroquad::audio::{load_sound, Sound};
 use macroquad::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+use crate::core::event::Event;
 use crate::core::hex::*;
+use crate::core::input::*;
+use crate::core::level::Level;
 use crate::core::map::*;
+use crate::core::pack::*;
+use crate::core::replay::Replay;
+use crate::core::rng::Rng;
 use crate::HEXES_SIZE;
 
+// Middle of the top face of the tile sprite, relative to `hex_to_pixel`
+pub const TILE_CENTER: Vec2 = Vec2::new(16.0, 11.0);
+
+// How the block went over the edge
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fall {
+    // The move that did it
+    pub direction: HexDirection,
+    // Hexes the block landed on that aren't on the map, the first is always
+    // there
+    pub off_map: [Option<Hex>; 2],
+    // The half of a lying block that was still on the map, it tips over
+    // this edge
+    pub on_map: Option<Hex>,
+}
+
+impl Fall {
+    fn new(direction: HexDirection, landing: &[Hex], map: &HexMap) -> Self {
+        let mut off_map = landing.iter().copied().filter(|hex| !map.is_in_map(*hex));
+        let on_map = landing.iter().copied().find(|hex| map.is_in_map(*hex));
+
+        Fall {
+            direction,
+            off_map: [off_map.next(), off_map.next()],
+            on_map,
+        }
+    }
+
+    pub fn off_map_hexes(&self) -> Vec<Hex> {
+        self.off_map.iter().flatten().copied().collect()
+    }
+}
+
+impl fmt::Display for Fall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hexes = self
+            .off_map_hexes()
+            .iter()
+            .map(|hex| format!("({}, {})", hex.q, hex.r))
+            .collect::<Vec<_>>()
+            .join(" and ");
+        write!(
+            f,
+            "Rolled {} off the map at {}",
+            self.direction.compass(),
+            hexes
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PlayerState {
     Standing(Hex),
     Flat(Hex, Hex),
-    Dead,
+    Dead(Fall),
 }
 
 impl PlayerState {
+    pub fn hexes(&self) -> Vec<Hex> {
+        match self {
+            PlayerState::Standing(hex) => vec![*hex],
+            PlayerState::Flat(head, tail) => vec![*head, *tail],
+            PlayerState::Dead(_) => vec![],
+        }
+    }
+
     pub fn next_state(&self, direction: HexDirection) -> Self {
         let delta = direction.to_hex();
 
@@ -31,36 +101,57 @@ impl PlayerState {
                     PlayerState::Flat(*head + delta, *tail + delta)
                 }
             }
-            _ => PlayerState::Dead,
+            // Nothing moves once it's fallen
+            PlayerState::Dead(fall) => PlayerState::Dead(*fall),
         }
     }
 
-    pub fn next_state_in_map(&self, direction: HexDirection, hexmap: &HashMap<Hex, bool>) -> Self {
+    pub fn next_state_in_map(&self, direction: HexDirection, map: &HexMap) -> Self {
         let state = self.next_state(direction);
+        let landing = state.hexes();
 
-        match state {
-            PlayerState::Standing(hex) => {
-                if hexmap.contains_key(&hex) {
-                    PlayerState::Standing(hex)
-                } else {
-                    PlayerState::Dead
-                }
-            }
-            PlayerState::Flat(head, tail) => {
-                if hexmap.contains_key(&head) && hexmap.contains_key(&tail) {
-                    PlayerState::Flat(head, tail)
-                } else {
-                    PlayerState::Dead
-                }
-            }
-            _ => PlayerState::Dead,
+        if landing.iter().all(|hex| map.is_in_map(*hex)) {
+            state
+        } else {
+            PlayerState::Dead(Fall::new(direction, &landing, map))
         }
     }
+
+    pub fn is_dead(&self) -> bool {
+        matches!(self, PlayerState::Dead(_))
+    }
+
+    // One move and the events it sets off, `None` once the attempt has been
+    // lost or won
+    pub fn step(&self, direction: HexDirection, map: &HexMap) -> Option<(Self, Vec<Event>)> {
+        if self.is_dead() || *self == PlayerState::Standing(map.goal) {
+            return None;
+        }
+
+        let to = self.next_state_in_map(direction, map);
+        let mut events = vec![Event::Moved(direction)];
+        if let PlayerState::Dead(fall) = to {
+            events.push(Event::Fell { from: *self, fall });
+        } else {
+            events.push(Event::Rolled { from: *self, to });
+        }
+        if to == PlayerState::Standing(map.goal) {
+            events.push(Event::Won);
+        }
+
+        Some((to, events))
+    }
 }
 
 pub enum GameState {
     MainMenu,
+    LevelSelect,
     Playing,
+    Paused,
+    Editor,
+    ReplaySelect,
+    Replay,
+    LevelError,
     GameOver,
     GameWon,
 }
@@ -70,17 +161,57 @@ pub struct Game {
     pub player_state: PlayerState,
     pub map: HexMap,
     pub level_count: u32,
+    pub packs: Vec<LevelPack>,
+    pub pack_index: usize,
+    pub completed: HashSet<(usize, usize)>,
+    pub level_error: Option<String>,
+    // Problems found while loading the packs, listed on the level select
+    pub pack_errors: Vec<String>,
+    pub is_custom: bool,
+    // Falls a run can take before it starts over from the first level,
+    // `None` to retry as often as it takes
+    pub max_lives: Option<u32>,
+    // Falls left in the current run
+    pub lives: u32,
+    // Falls in the current run, counted with unlimited lives too so changing
+    // the setting mid-run doesn't hand out a fresh set
+    falls: u32,
+    // What the generator made the map from, 0 for hand-made levels
+    pub seed: u64,
+    // Moves of the current attempt, from when the map was put on the board
+    pub replay: Replay,
+    // Off while a replay plays back, so watching one doesn't save another
+    pub is_recording: bool,
+    // Where the attempt's replay is saved, `None` once it has been
+    replay_label: Option<String>,
+    // Why the last replay couldn't be saved, for `main.rs` to show
+    pub save_error: Option<String>,
+    attempt_start: f64,
+    // When the attempt was paused, the pause doesn't count towards its times
+    paused_at: Option<f64>,
+    // States before each move of the attempt, for undo
+    history: Vec<PlayerState>,
+    events: Vec<Event>,
     tile_texture: Texture2D,
     standing_texture: Texture2D,
     flat_diag_main_texture: Texture2D,
     flat_diag_other_texture: Texture2D,
     flat_w_texture: Texture2D,
     flat_e_texture: Texture2D,
+    #[cfg(feature = "audio")]
     pub sound_explosion: Sound,
+    #[cfg(feature = "audio")]
     pub theme_music: Sound,
     map_zoom: f32,
+    // Board rotation in 60 degree steps, only affects how the map is shown
+    pub rotation: i32,
+    // Keeps the board still while the map changes under it, e.g. in the editor
+    pub fixed_center: Option<Vec2>,
 }
 
+// Choices for `Game::max_lives`
+pub const LIVES_OPTIONS: [Option<u32>; 4] = [None, Some(1), Some(3), Some(5)];
+
 // const MAP_ZOOM: f32 = 2.0;
 impl Game {
     pub async fn init() -> Self {
@@ -93,139 +224,356 @@ impl Game {
             load_texture("hex_flat_diag_other.png").await.unwrap();
         let flat_w_texture: Texture2D = load_texture("hex_flat_w.png").await.unwrap();
         let flat_e_texture: Texture2D = load_texture("hex_flat_e.png").await.unwrap();
+        #[cfg(feature = "audio")]
         let sound_explosion: Sound = load_sound("explosion.wav").await.unwrap();
+        #[cfg(feature = "audio")]
         let theme_music: Sound = load_sound("through_space.ogg").await.unwrap();
 
-        let pointy: Layout = Layout {
-            orientation: Orientation::LAYOUT_POINTY,
-            // Point(W/sqrt(3), H/2)
-            size: Vec2 {
-                x: 31.0 / SQRT_3,
-                y: 21.0 / 2.0,
-            },
-            origin: Vec2 {
-                x: screen_width() / 2.0,
-                y: screen_height() / 2.0,
-            },
-        };
+        let (mut packs, pack_errors) = LevelPack::load_all().await;
+        if packs.is_empty() {
+            packs.push(LevelPack::builtin());
+        }
 
         let game_map = HexMap::gen();
+        let replay = Replay::new(&game_map, 0);
 
         Game {
-            layout: pointy.clone(),
+            layout: Game::layout_for(Orientation::LAYOUT_POINTY),
             player_state: PlayerState::Standing(game_map.start),
             map: game_map,
             level_count: 0,
+            packs,
+            pack_index: 0,
+            completed: HashSet::new(),
+            level_error: None,
+            pack_errors,
+            is_custom: false,
+            max_lives: None,
+            lives: 0,
+            falls: 0,
+            seed: 0,
+            replay,
+            is_recording: true,
+            replay_label: None,
+            save_error: None,
+            attempt_start: get_time(),
+            paused_at: None,
+            history: Vec::new(),
+            events: Vec::new(),
             tile_texture,
             standing_texture,
             flat_diag_main_texture,
             flat_diag_other_texture,
             flat_w_texture,
             flat_e_texture,
+            #[cfg(feature = "audio")]
             sound_explosion,
+            #[cfg(feature = "audio")]
             theme_music,
             map_zoom: 2.0,
+            rotation: 0,
+            fixed_center: None,
         }
     }
 
-    fn update_map(&mut self, game_map: HexMap) {
+    // Both orientations keep the 31x21 top face of the tile sprite
+    fn layout_for(orientation: Orientation) -> Layout {
+        let size = if orientation == Orientation::LAYOUT_POINTY {
+            // Point(W/sqrt(3), H/2)
+            vec2(31.0 / SQRT_3, 21.0 / 2.0)
+        } else {
+            // Point(W/2, H/sqrt(3))
+            vec2(31.0 / 2.0, 21.0 / SQRT_3)
+        };
+
+        Layout {
+            orientation,
+            size,
+            origin: Vec2 {
+                x: screen_width() / 2.0,
+                y: screen_height() / 2.0,
+            },
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.layout.orientation == Orientation::LAYOUT_FLAT
+    }
+
+    pub fn set_flat(&mut self, is_flat: bool) {
+        let orientation = if is_flat {
+            Orientation::LAYOUT_FLAT
+        } else {
+            Orientation::LAYOUT_POINTY
+        };
+        self.layout = Game::layout_for(orientation);
+    }
+
+    pub fn rotate_view(&mut self, steps: i32) {
+        self.rotation = (self.rotation + steps).rem_euclid(6);
+    }
+
+    // Where a map hex is shown once the board is rotated
+    fn view(&self, hex: Hex) -> Hex {
+        hex.rotate(Hex::from_axial(0, 0), self.rotation)
+    }
+
+    pub fn hex_to_world(&self, hex: Hex) -> Vec2 {
+        self.layout.hex_to_pixel(self.view(hex))
+    }
+
+    // Direction `step` * 60 degrees counter-clockwise from pointing right on
+    // screen. With the flat layout nothing points right, so step 0 takes the
+    // first direction above it and the rest follow in order.
+    pub fn screen_direction(&self, step: usize) -> HexDirection {
+        let origin = self.hex_to_world(Hex::from_axial(0, 0));
+        let angle = |dir: &HexDirection| {
+            let v = self.hex_to_world(dir.to_hex()) - origin;
+            // Nudged so rounding can't push a direction pointing right last
+            ((-v.y).atan2(v.x).to_degrees() + 1.0).rem_euclid(360.0)
+        };
+
+        let mut dirs = HEX_DIRECTIONS;
+        dirs.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+
+        dirs[step % 6]
+    }
+
+    pub fn update_map(&mut self, game_map: HexMap) {
+        self.end_attempt();
+
         self.player_state = PlayerState::Standing(game_map.start);
+        self.replay = Replay::new(&game_map, self.seed);
+        self.replay_label = self.is_recording.then(|| {
+            if self.is_custom {
+                "custom".to_owned()
+            } else {
+                format!(
+                    "pack-{}-level-{}",
+                    self.pack_index + 1,
+                    self.level_count + 1
+                )
+            }
+        });
+        self.attempt_start = get_time();
+        self.paused_at = None;
+        self.history.clear();
+        self.events.push(Event::LevelLoaded);
         self.map = game_map;
     }
 
     pub fn update_level(&mut self, is_next: bool) {
+        self.is_recording = true;
         if is_next {
             self.level_count += 1;
         }
 
-        match self.level_count {
-            0 => {
-                self.update_map(load_map(HexMap::PREMADE_MAP_0));
-            }
-            1 => {
-                self.update_map(load_map(HexMap::PREMADE_MAP_1));
-            }
-            2 => {
-                self.update_map(load_map(HexMap::PREMADE_MAP_2));
-            }
-            3 => {
-                self.update_map(load_map(HexMap::PREMADE_MAP_3));
+        self.level_error = None;
+        self.is_custom = false;
+
+        // Once the pack runs out, keep going with random maps
+        match self.current_level() {
+            Some(Ok(level)) => {
+                let game_map = level.map.clone();
+                self.seed = 0;
+                self.update_map(game_map);
             }
-            _ => {
-                self.update_map(HexMap::gen());
+            Some(Err(error)) => {
+                self.level_error = Some(error.clone());
             }
+            None => loop {
+                let seed = Rng::random_seed();
+                let game_map = HexMap::gen_with(&mut Rng::new(seed));
+                if game_map.validate().is_ok() {
+                    self.seed = seed;
+                    self.update_map(game_map);
+                    break;
+                }
+            },
         }
 
         self.map_zoom = 2.0;
     }
 
+    // Plays a map from outside the level packs, e.g. a pasted level code
+    pub fn play_map(&mut self, game_map: HexMap) {
+        self.is_recording = true;
+        self.level_error = None;
+        self.is_custom = true;
+        self.seed = 0;
+        self.update_map(game_map);
+        self.map_zoom = 2.0;
+    }
+
+    pub fn select_level(&mut self, pack_index: usize, level_index: usize) {
+        self.pack_index = pack_index;
+        self.level_count = level_index as u32;
+        self.reset_lives();
+        self.update_level(false);
+    }
+
+    pub fn current_level(&self) -> Option<&Result<Level, String>> {
+        self.packs
+            .get(self.pack_index)?
+            .levels
+            .get(self.level_count as usize)
+    }
+
+    pub fn complete_level(&mut self) {
+        if self.current_level().is_some() {
+            self.completed
+                .insert((self.pack_index, self.level_count as usize));
+        }
+    }
+
+    pub fn is_completed(&self, pack_index: usize, level_index: usize) -> bool {
+        self.completed.contains(&(pack_index, level_index))
+    }
+
     fn move_player(&mut self, direction: HexDirection) {
-        let delta = direction.to_hex();
-        if delta == Hex::from_axial(0, 0) {
+        // Moves queued up behind a fall or a win in the same frame are
+        // dropped, so a fall only costs one life
+        let Some((state, events)) = self.player_state.step(direction, &self.map) else {
             return;
+        };
+
+        self.history.push(self.player_state);
+        let time = get_time() - self.attempt_start;
+        self.replay.inputs.push((time, direction));
+        self.player_state = state;
+        self.events.extend(events);
+    }
+
+    // Events since the last call, oldest first
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn screen_to_hex(&self, screen: Vec2) -> Hex {
+        let world = self.camera().screen_to_world(screen);
+        let hex = self.layout.pixel_to_hex(world - TILE_CENTER);
+        hex.rotate(Hex::from_axial(0, 0), -self.rotation)
+    }
+
+    // The move that would put the block on `hex`, if there is one
+    pub fn direction_towards(&self, hex: Hex) -> Option<HexDirection> {
+        let current = self.player_state.hexes();
+        if current.contains(&hex) {
+            return None;
         }
 
-        let hexmap = &self.map.hexmap;
+        HEX_DIRECTIONS
+            .into_iter()
+            .find(|dir| self.player_state.next_state(*dir).hexes().contains(&hex))
+    }
 
-        self.player_state = match self.player_state {
-            PlayerState::Standing(head) => PlayerState::Flat(head + delta * 2, head + delta),
-            PlayerState::Flat(head, tail) => {
-                let diff = HexDirection::get_dir_from_to(tail, head);
+    pub fn update(&mut self, input: &mut impl InputSource) {
+        for action in input.actions(self) {
+            self.apply_action(action);
+        }
+    }
 
-                if diff == direction {
-                    PlayerState::Standing(head + delta)
-                } else if diff == direction.opposite() {
-                    PlayerState::Standing(tail + delta)
-                } else {
-                    PlayerState::Flat(head + delta, tail + delta)
-                }
-            }
-            _ => PlayerState::Dead,
-        };
+    pub fn apply_action(&mut self, action: Action) {
+        match action {
+            Action::Move(dir) => self.move_player(dir),
+            Action::Zoom(delta) => self.zoom(delta),
+            Action::Rotate(steps) => self.rotate_view(steps),
+            Action::ToggleFlat => self.set_flat(!self.is_flat()),
+            Action::Undo => self.undo(),
+            Action::Restart => self.restart(),
+        }
+    }
 
-        self.player_state = match self.player_state {
-            PlayerState::Standing(hex) => {
-                if hexmap.contains_key(&hex) {
-                    PlayerState::Standing(hex)
-                } else {
-                    PlayerState::Dead
-                }
-            }
-            PlayerState::Flat(head, tail) => {
-                if hexmap.contains_key(&head) && hexmap.contains_key(&tail) {
-                    PlayerState::Flat(head, tail)
-                } else {
-                    PlayerState::Dead
-                }
-            }
-            _ => PlayerState::Dead,
+    // Takes back the last move, the replay forgets it too so it still plays
+    // back to the same place
+    pub fn undo(&mut self) {
+        if let Some(state) = self.history.pop() {
+            let from = self.player_state;
+            self.player_state = state;
+            self.replay.inputs.pop();
+            self.events.push(Event::Undone { from, to: state });
+        }
+    }
+
+    // Saves the attempt's replay, once, if any moves were made. Every new
+    // attempt ends the last one, and `main.rs` calls this on a fall or a
+    // win and when leaving for a menu.
+    pub fn end_attempt(&mut self) {
+        let Some(label) = self.replay_label.take() else {
+            return;
         };
+        if self.replay.inputs.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.replay.save(&label) {
+            self.save_error = Some(e);
+        }
     }
 
-    pub fn update(&mut self) {
-        if let Some(key) = get_last_key_pressed() {
-            let k = match key {
-                KeyCode::Up => self.map_zoom += 0.5,
-                KeyCode::Down => self.map_zoom -= 0.5,
-                _ => {}
-            };
-
-            let dir: Option<HexDirection> = match key {
-                KeyCode::W => Some(HexDirection::NW),
-                KeyCode::E => Some(HexDirection::NE),
-                KeyCode::D => Some(HexDirection::E),
-                KeyCode::A => Some(HexDirection::W),
-                KeyCode::Z => Some(HexDirection::SW),
-                KeyCode::X => Some(HexDirection::SE),
-                _ => None,
-            };
-
-            if let Some(dir) = dir {
-                self.move_player(dir);
-            }
+    pub fn stop_recording(&mut self) {
+        self.is_recording = false;
+        self.replay_label = None;
+    }
+
+    // Back to the start of the same map as a new attempt
+    pub fn restart(&mut self) {
+        self.update_map(self.map.clone());
+    }
+
+    // A new run from the first level of the pack
+    pub fn reset_run(&mut self) {
+        self.level_count = 0;
+        self.reset_lives();
+    }
+
+    // Steps through `LIVES_OPTIONS`, back for a negative `step`. The run
+    // keeps its falls, but always has at least one life left under the new
+    // rule.
+    pub fn cycle_lives(&mut self, step: i32) {
+        let count = LIVES_OPTIONS.len() as i32;
+        let i = LIVES_OPTIONS
+            .iter()
+            .position(|lives| *lives == self.max_lives)
+            .map_or(0, |i| (i as i32 + step).rem_euclid(count) as usize);
+        self.max_lives = LIVES_OPTIONS[i];
+        self.lives = self
+            .max_lives
+            .map_or(0, |lives| lives.saturating_sub(self.falls).max(1));
+    }
+
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert(get_time());
+    }
+
+    // Moves the attempt's start on by the time spent paused, so the replay
+    // doesn't wait through it
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.attempt_start += get_time() - paused_at;
         }
     }
 
+    pub fn reset_lives(&mut self) {
+        self.lives = self.max_lives.unwrap_or(0);
+        self.falls = 0;
+    }
+
+    // Custom levels are practice, they don't count against the run
+    pub fn lose_life(&mut self) {
+        if self.is_custom {
+            return;
+        }
+        self.falls += 1;
+        if self.max_lives.is_some() {
+            self.lives = self.lives.saturating_sub(1);
+        }
+    }
+
+    pub fn is_out_of_lives(&self) -> bool {
+        self.max_lives.is_some() && !self.is_custom && self.lives == 0
+    }
+
     fn draw_flat(&self, state: PlayerState) {
         let head = match state {
             PlayerState::Flat(head, _) => head,
@@ -236,8 +584,8 @@ impl Game {
             _ => Hex::from_axial(0, 0),
         };
 
-        let head_pixel = self.layout.hex_to_pixel(head) + vec2(16.0, 11.0);
-        let tail_pixel = self.layout.hex_to_pixel(tail) + vec2(16.0, 11.0);
+        let head_pixel = self.hex_to_world(head) + TILE_CENTER;
+        let tail_pixel = self.hex_to_world(tail) + TILE_CENTER;
 
         draw_line(
             tail_pixel.x,
@@ -262,8 +610,8 @@ impl Game {
             _ => Hex::from_axial(0, 0),
         };
 
-        let head_pixel = self.layout.hex_to_pixel(head) + vec2(16.0, 11.0);
-        let next_pixel = self.layout.hex_to_pixel(next) + vec2(16.0, 11.0);
+        let head_pixel = self.hex_to_world(head) + TILE_CENTER;
+        let next_pixel = self.hex_to_world(next) + TILE_CENTER;
 
         draw_line(
             head_pixel.x,
@@ -299,17 +647,61 @@ impl Game {
         }
     }
 
+    // Outlines where the block would land when clicking the hovered hex,
+    // call after `draw` while its camera is set
+    pub fn draw_hover(&self) {
+        let hex = self.screen_to_hex(mouse_position().into());
+        let Some(dir) = self.direction_towards(hex) else {
+            return;
+        };
+
+        let next = self.player_state.next_state_in_map(dir, &self.map);
+        let color = if next.is_dead() { RED } else { YELLOW };
+
+        for hex in self.player_state.next_state(dir).hexes() {
+            self.draw_hex_outline(hex, color);
+        }
+    }
+
+    pub fn draw_hex_outline(&self, hex: Hex, color: Color) {
+        let corners = self.layout.polygon_corners(self.view(hex));
+
+        for i in 0..6 {
+            let a = corners[i] + TILE_CENTER;
+            let b = corners[(i + 1) % 6] + TILE_CENTER;
+            draw_line(a.x, a.y, b.x, b.y, 1.0, color);
+        }
+    }
+
+    pub fn draw_hex_filled(&self, hex: Hex, color: Color) {
+        let corners = self.layout.polygon_corners(self.view(hex));
+        let center = self.hex_to_world(hex) + TILE_CENTER;
+
+        for i in 0..6 {
+            let a = corners[i] + TILE_CENTER;
+            let b = corners[(i + 1) % 6] + TILE_CENTER;
+            draw_triangle(center, a, b, color);
+        }
+    }
+
+    // Back to front, so sprites lower on screen overlap the ones above
+    fn sorted_tiles(&self) -> Vec<Hex> {
+        let mut hexes = self.map.hexmap.iter().collect::<Vec<_>>();
+
+        hexes.sort_by(|a, b| {
+            let a = self.hex_to_world(*a);
+            let b = self.hex_to_world(*b);
+            a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
+        });
+
+        hexes
+    }
+
     pub fn draw_tiles(&self, texture: &Texture2D) {
-        let hexmap = &self.map.hexmap;
         let goal = self.map.goal;
-        let v = hexmap.keys().collect::<Vec<_>>();
-        let mut hexes = v.iter().map(|hex| hex.to_offset()).collect::<Vec<_>>();
 
-        hexes.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
-
-        for hex in hexes {
-            let h = Hex::from_offset(hex);
-            let pixel = self.layout.hex_to_pixel(h);
+        for h in self.sorted_tiles() {
+            let pixel = self.hex_to_world(h);
 
             if h == goal {
                 // draw_texture(texture, pixel.x, pixel.y, BLACK);
@@ -331,10 +723,12 @@ impl Game {
     ) {
         match self.player_state {
             PlayerState::Standing(hex) => {
-                let pixel = self.layout.hex_to_pixel(hex) + vec2(0.0, -32.0);
+                let pixel = self.hex_to_world(hex) + vec2(0.0, -32.0);
                 draw_texture(standing_texture, pixel.x, pixel.y, WHITE);
             }
             PlayerState::Flat(head, tail) => {
+                // The sprites are picked by how the block lies on screen
+                let (head, tail) = (self.view(head), self.view(tail));
                 let dir = HexDirection::get_dir_from_to(head, tail);
 
                 match dir {
@@ -368,24 +762,84 @@ impl Game {
         }
     }
 
-    pub fn draw(&self, is_debug: bool) {
-        set_camera(&Camera2D {
+    // The sprites only fit the pointy layout, anything else is drawn with
+    // plain hex polygons
+    pub fn draw_vector(&self) {
+        for hex in self.sorted_tiles() {
+            if hex == self.map.goal {
+                self.draw_hex_outline(hex, RED);
+            } else {
+                self.draw_hex_filled(hex, Color::from_hex(0x5a6988));
+                self.draw_hex_outline(hex, Color::from_hex(0x3a4466));
+            }
+        }
+
+        let block = Color::from_hex(0x9b4747);
+        match self.player_state {
+            PlayerState::Standing(hex) => {
+                self.draw_hex_filled(hex, block);
+                self.draw_hex_outline(hex, WHITE);
+            }
+            PlayerState::Flat(head, tail) => {
+                let head_pixel = self.hex_to_world(head) + TILE_CENTER;
+                let tail_pixel = self.hex_to_world(tail) + TILE_CENTER;
+
+                self.draw_hex_filled(head, block);
+                self.draw_hex_filled(tail, block);
+                draw_line(
+                    head_pixel.x,
+                    head_pixel.y,
+                    tail_pixel.x,
+                    tail_pixel.y,
+                    HEXES_SIZE / 10.0,
+                    WHITE,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    pub fn camera(&self) -> Camera2D {
+        Camera2D {
             zoom: vec2(
                 self.map_zoom / screen_width() * 2.0,
                 self.map_zoom / screen_height() * 2.0,
             ),
-            target: self.get_center(),
+            target: self.fixed_center.unwrap_or_else(|| self.get_center()),
             ..Default::default()
-        });
+        }
+    }
 
-        self.draw_tiles(&self.tile_texture);
-        self.draw_player_hex(
-            &self.standing_texture,
-            &self.flat_diag_main_texture,
-            &self.flat_diag_other_texture,
-            &self.flat_e_texture,
-            &self.flat_w_texture,
-        );
+    pub fn zoom(&mut self, delta: f32) {
+        self.map_zoom = (self.map_zoom + delta).max(0.5);
+    }
+
+    pub fn draw(&self, is_debug: bool) {
+        set_camera(&self.camera());
+
+        if self.is_flat() {
+            self.draw_vector();
+        } else {
+            self.draw_tiles(&self.tile_texture);
+            self.draw_player_hex(
+                &self.standing_texture,
+                &self.flat_diag_main_texture,
+                &self.flat_diag_other_texture,
+                &self.flat_e_texture,
+                &self.flat_w_texture,
+            );
+        }
+
+        // Where the block went over, the half still on the map is the edge
+        // it tipped over
+        if let PlayerState::Dead(fall) = self.player_state {
+            for hex in fall.off_map_hexes() {
+                self.draw_hex_outline(hex, RED);
+            }
+            if let Some(hex) = fall.on_map {
+                self.draw_hex_outline(hex, ORANGE);
+            }
+        }
 
         if is_debug {
             if let PlayerState::Standing(hex) = self.player_state {
@@ -396,13 +850,13 @@ impl Game {
         }
     }
 
-    fn get_center(&self) -> Vec2 {
+    pub fn get_center(&self) -> Vec2 {
         let hexmap = &self.map.hexmap;
         let mut top_left = vec2(f32::MAX, f32::MAX);
         let mut bottom_right = vec2(f32::MIN, f32::MIN);
 
-        for hex in hexmap.keys() {
-            let pixel = self.layout.hex_to_pixel(*hex);
+        for hex in hexmap.iter() {
+            let pixel = self.hex_to_world(hex);
 
             top_left.x = top_left.x.min(pixel.x);
             top_left.y = top_left.y.min(pixel.y);
@@ -417,3 +871,35 @@ impl Game {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_happens_after_a_fall() {
+        let map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        let start = PlayerState::Standing(map.start);
+
+        let (fallen, events) = start.step(HexDirection::W, &map).unwrap();
+        assert!(fallen.is_dead());
+        assert!(matches!(events[..], [Event::Moved(_), Event::Fell { .. }]));
+
+        assert_eq!(fallen.step(HexDirection::E, &map), None);
+    }
+
+    #[test]
+    fn nothing_happens_after_a_win() {
+        let map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        let mut state = PlayerState::Standing(map.start);
+        let mut events = Vec::new();
+
+        for dir in map.solve().moves.unwrap() {
+            let (next, new_events) = state.step(dir, &map).unwrap();
+            state = next;
+            events.extend(new_events);
+        }
+        assert_eq!(events.last(), Some(&Event::Won));
+        assert_eq!(state.step(HexDirection::E, &map), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::core::hex::*;
+
+// Set of tiles stored as a dense axial rectangle, `cells[r * width + q]`.
+// Lookups are a bounds check and an index, and iteration goes row by row so
+// it's the same every run. The rectangle grows as tiles are added outside it.
+#[derive(Debug, Clone, Default)]
+pub struct HexGrid {
+    min_q: i32,
+    min_r: i32,
+    width: i32,
+    height: i32,
+    cells: Vec<bool>,
+    len: usize,
+}
+
+// Room left around the tiles when the grid grows, so drawing a map tile by
+// tile doesn't reallocate every time
+const GROW_MARGIN: i32 = 2;
+
+// Most cells the rectangle may cover, so a few tiles far apart can't ask
+// for a huge allocation
+pub const MAX_CELLS: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridTooLarge;
+
+impl fmt::Display for GridTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tiles are spread over more than {} cells", MAX_CELLS)
+    }
+}
+
+impl std::error::Error for GridTooLarge {}
+
+impl HexGrid {
+    pub fn new() -> Self {
+        HexGrid::default()
+    }
+
+    fn index(&self, hex: Hex) -> Option<usize> {
+        // Widened so hexes near the ends of i32 can't overflow
+        let q = hex.q as i64 - self.min_q as i64;
+        let r = hex.r as i64 - self.min_r as i64;
+
+        if q < 0 || r < 0 || q >= self.width as i64 || r >= self.height as i64 {
+            return None;
+        }
+
+        Some((r * self.width as i64 + q) as usize)
+    }
+
+    pub fn contains(&self, hex: Hex) -> bool {
+        self.index(hex).is_some_and(|i| self.cells[i])
+    }
+
+    // Returns whether the tile is new
+    pub fn insert(&mut self, hex: Hex) -> Result<bool, GridTooLarge> {
+        if self.index(hex).is_none() {
+            self.grow(hex)?;
+        }
+
+        let i = self.index(hex).unwrap();
+        if self.cells[i] {
+            return Ok(false);
+        }
+
+        self.cells[i] = true;
+        self.len += 1;
+        Ok(true)
+    }
+
+    pub fn try_from_iter(hexes: impl IntoIterator<Item = Hex>) -> Result<Self, GridTooLarge> {
+        let mut grid = HexGrid::new();
+        for hex in hexes {
+            grid.insert(hex)?;
+        }
+        Ok(grid)
+    }
+
+    // Returns whether the tile was there
+    pub fn remove(&mut self, hex: Hex) -> bool {
+        match self.index(hex) {
+            Some(i) if self.cells[i] => {
+                self.cells[i] = false;
+                self.len -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn grow(&mut self, hex: Hex) -> Result<(), GridTooLarge> {
+        let (q, r) = (hex.q as i64, hex.r as i64);
+        // From the tiles rather than the old rectangle, its margin doesn't
+        // count against the cap
+        let (min_q, min_r, max_q, max_r) = match self.bounds() {
+            None => (q, r, q, r),
+            Some((min, max)) => (
+                (min.q as i64).min(q),
+                (min.r as i64).min(r),
+                (max.q as i64).max(q),
+                (max.r as i64).max(r),
+            ),
+        };
+
+        // Everything in i64, the margin can push past the ends of i32. The
+        // cap is on the tiles' own rectangle, so any map that fits also fits
+        // in a level code.
+        let cells = (max_q - min_q + 1).checked_mul(max_r - min_r + 1);
+        if cells.is_none_or(|cells| cells > MAX_CELLS as i64) {
+            return Err(GridTooLarge);
+        }
+
+        // The margin is left out where it would go past the cap or i32
+        let rect = |margin: i64| {
+            let width = max_q - min_q + 1 + 2 * margin;
+            let height = max_r - min_r + 1 + 2 * margin;
+            i32::try_from(max_q + margin).ok()?;
+            i32::try_from(max_r + margin).ok()?;
+            Some((
+                i32::try_from(min_q - margin).ok()?,
+                i32::try_from(min_r - margin).ok()?,
+                i32::try_from(width).ok()?,
+                i32::try_from(height).ok()?,
+            ))
+            .filter(|_| width * height <= MAX_CELLS as i64)
+        };
+        let (min_q, min_r, width, height) = rect(GROW_MARGIN as i64)
+            .or_else(|| rect(0))
+            .ok_or(GridTooLarge)?;
+
+        let mut grown = HexGrid {
+            min_q,
+            min_r,
+            width,
+            height,
+            cells: vec![false; width as usize * height as usize],
+            len: 0,
+        };
+
+        for tile in self.iter() {
+            let i = grown.index(tile).unwrap();
+            grown.cells[i] = true;
+            grown.len += 1;
+        }
+
+        *self = grown;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Tiles row by row, then by q
+    pub fn iter(&self) -> impl Iterator<Item = Hex> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| **cell)
+            .map(|(i, _)| {
+                let i = i as i32;
+                Hex::from_axial(self.min_q + i % self.width, self.min_r + i / self.width)
+            })
+    }
+
+    // Smallest and largest axial q and r of the tiles, as two corner hexes
+    pub fn bounds(&self) -> Option<(Hex, Hex)> {
+        let mut tiles = self.iter();
+        let first = tiles.next()?;
+
+        let (mut min_q, mut max_q) = (first.q, first.q);
+        let (min_r, mut max_r) = (first.r, first.r);
+
+        for hex in tiles {
+            min_q = min_q.min(hex.q);
+            max_q = max_q.max(hex.q);
+            max_r = hex.r;
+        }
+
+        Some((Hex::from_axial(min_q, min_r), Hex::from_axial(max_q, max_r)))
+    }
+}
+
+impl PartialEq for HexGrid {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|hex| other.contains(hex))
+    }
+}
+
+// Stored as the list of tiles
+#[cfg(feature = "serde")]
+impl Serialize for HexGrid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for HexGrid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tiles = Vec::<Hex>::deserialize(deserializer)?;
+        HexGrid::try_from_iter(tiles).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_far_apart_are_rejected() {
+        let mut grid = HexGrid::new();
+        assert_eq!(grid.insert(Hex::from_axial(0, 0)), Ok(true));
+        assert_eq!(grid.insert(Hex::from_axial(5000, 5000)), Err(GridTooLarge));
+        // Nothing changed
+        assert_eq!(grid.len(), 1);
+        assert!(grid.contains(Hex::from_axial(0, 0)));
+    }
+
+    #[test]
+    fn ends_of_i32_dont_overflow() {
+        // A lone tile at the end fits without its margin
+        let mut grid = HexGrid::new();
+        assert_eq!(grid.insert(Hex::from_axial(i32::MAX, 0)), Ok(true));
+        assert!(grid.contains(Hex::from_axial(i32::MAX, 0)));
+        assert_eq!(
+            grid.insert(Hex::from_axial(0, i32::MIN + 1)),
+            Err(GridTooLarge)
+        );
+
+        let mut grid = HexGrid::new();
+        grid.insert(Hex::from_axial(-3, 4)).unwrap();
+        assert!(!grid.contains(Hex::from_axial(i32::MAX, i32::MIN)));
+        assert!(!grid.contains(Hex::from_axial(i32::MIN + 1, i32::MAX)));
+        assert_eq!(
+            grid.insert(Hex::from_axial(i32::MIN + 4, 4)),
+            Err(GridTooLarge)
+        );
+    }
+
+    #[test]
+    fn opposite_corners_dont_overflow() {
+        let mut grid = HexGrid::new();
+        grid.insert(Hex::from_axial(-2147483645, 2147483644))
+            .unwrap();
+        assert_eq!(
+            grid.insert(Hex::from_axial(2147483644, -2147483645)),
+            Err(GridTooLarge)
+        );
+        assert_eq!(grid.len(), 1);
+    }
+
+    #[test]
+    fn cap_is_on_the_tiles_not_the_margin() {
+        // Exactly `MAX_CELLS` in one row, with no room left for a margin
+        let mut grid = HexGrid::new();
+        grid.insert(Hex::from_axial(0, 0)).unwrap();
+        assert_eq!(
+            grid.insert(Hex::from_axial(MAX_CELLS as i32 - 1, 0)),
+            Ok(true)
+        );
+        assert_eq!(
+            grid.insert(Hex::from_axial(MAX_CELLS as i32, 0)),
+            Err(GridTooLarge)
+        );
+        assert_eq!(
+            grid.bounds().unwrap().1,
+            Hex::from_axial(MAX_CELLS as i32 - 1, 0)
+        );
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut grid = HexGrid::new();
+        let hexes = [(2, -1), (0, 0), (-4, 3), (1, 1)].map(|(q, r)| Hex::from_axial(q, r));
+        for hex in hexes {
+            assert_eq!(grid.insert(hex), Ok(true));
+        }
+        assert_eq!(grid.insert(hexes[0]), Ok(false));
+        assert_eq!(grid.len(), 4);
+
+        // Row by row, then by q
+        let order = grid.iter().map(<(i32, i32)>::from).collect::<Vec<_>>();
+        assert_eq!(order, vec![(2, -1), (0, 0), (1, 1), (-4, 3)]);
+
+        assert!(grid.remove(hexes[1]));
+        assert!(!grid.remove(hexes[1]));
+        assert_eq!(grid.len(), 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_rejects_huge_grids() {
+        assert!(serde_json::from_str::<HexGrid>("[[0,0],[100000,100000]]").is_err());
+        let json = "[[-2147483645,2147483644],[2147483644,-2147483645]]";
+        assert!(serde_json::from_str::<HexGrid>(json).is_err());
+
+        let grid = serde_json::from_str::<HexGrid>("[[0,0],[1,2]]").unwrap();
+        assert_eq!(grid.len(), 2);
+    }
+}
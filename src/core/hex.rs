@@ -1,16 +1,25 @@
 use macroquad::math::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
 
 pub const SQRT_3: f32 = 1.732_050_8_f32;
 
+// Stored as the axial pair `[q, r]`, `s` always follows from them so a
+// stored hex can't break q + r + s = 0
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "(i32, i32)", into = "(i32, i32)")
+)]
 pub struct Hex {
     pub q: i32,
     pub r: i32,
     pub s: i32,
 }
 
-#[allow(dead_code)]
 impl Hex {
     pub fn from_cube(q: i32, r: i32, s: i32) -> Self {
         assert_eq!(q + r + s, 0);
@@ -46,10 +55,10 @@ impl Hex {
         let mut results = Vec::new();
         let mut h = *self + DIR[4] * radius;
 
-        for i in 0..6 {
+        for dir in DIR {
             for _ in 0..radius {
                 results.push(h);
-                h += DIR[i];
+                h += dir;
             }
         }
 
@@ -60,6 +69,115 @@ impl Hex {
         results
     }
 
+    pub fn length(&self) -> i32 {
+        (self.q.abs() + self.r.abs() + self.s.abs()) / 2
+    }
+
+    pub fn distance(&self, other: Hex) -> i32 {
+        (*self - other).length()
+    }
+
+    pub fn lerp(&self, other: Hex, t: f32) -> FractionalHex {
+        FractionalHex::from(*self).lerp(FractionalHex::from(other), t)
+    }
+
+    // Every hex on the straight line to `other`, both ends included
+    pub fn line_to(&self, other: Hex) -> Vec<Hex> {
+        let n = self.distance(other);
+
+        // Nudge both ends so points exactly on an edge round the same way
+        let nudge = FractionalHex {
+            q: 1e-6,
+            r: 1e-6,
+            s: -2e-6,
+        };
+        let a = FractionalHex::from(*self) + nudge;
+        let b = FractionalHex::from(other) + nudge;
+
+        (0..=n)
+            .map(|i| {
+                let t = if n == 0 { 0.0 } else { i as f32 / n as f32 };
+                a.lerp(b, t).round()
+            })
+            .collect()
+    }
+
+    // Rotates by `steps` * 60 degrees around `center`, positive steps go the
+    // same way as `DIR` (E, NE, NW, ...)
+    pub fn rotate(&self, center: Hex, steps: i32) -> Hex {
+        let mut v = *self - center;
+
+        for _ in 0..steps.rem_euclid(6) {
+            v = Hex {
+                q: -v.s,
+                r: -v.q,
+                s: -v.r,
+            };
+        }
+
+        center + v
+    }
+
+    // Mirrors around `center` by keeping the `axis` coordinate and swapping
+    // the other two, so the line left in place is where those two are equal
+    // (r == s for Q), not the line where `axis` is constant
+    pub fn reflect(&self, center: Hex, axis: HexAxis) -> Hex {
+        let v = *self - center;
+
+        let v = match axis {
+            HexAxis::Q => Hex {
+                q: v.q,
+                r: v.s,
+                s: v.r,
+            },
+            HexAxis::R => Hex {
+                q: v.s,
+                r: v.r,
+                s: v.q,
+            },
+            HexAxis::S => Hex {
+                q: v.r,
+                r: v.q,
+                s: v.s,
+            },
+        };
+
+        center + v
+    }
+
+    // Every hex within `radius` steps, row by row
+    pub fn range(&self, radius: i32) -> Vec<Hex> {
+        let mut results = Vec::new();
+
+        for r in -radius..=radius {
+            for q in (-radius).max(-r - radius)..=radius.min(-r + radius) {
+                results.push(*self + Hex::from_axial(q, r));
+            }
+        }
+
+        results
+    }
+
+    // Hexes within `radius` of self and within `other_radius` of `other`
+    pub fn range_intersection(&self, radius: i32, other: Hex, other_radius: i32) -> Vec<Hex> {
+        let mut results = Vec::new();
+
+        let q_min = (self.q - radius).max(other.q - other_radius);
+        let q_max = (self.q + radius).min(other.q + other_radius);
+        let r_min = (self.r - radius).max(other.r - other_radius);
+        let r_max = (self.r + radius).min(other.r + other_radius);
+        let s_min = (self.s - radius).max(other.s - other_radius);
+        let s_max = (self.s + radius).min(other.s + other_radius);
+
+        for q in q_min..=q_max {
+            for r in r_min.max(-q - s_max)..=r_max.min(-q - s_min) {
+                results.push(Hex::from_axial(q, r));
+            }
+        }
+
+        results
+    }
+
     pub fn spiral(&self, radius: i32) -> Vec<Hex> {
         let mut results = Vec::new();
         results.push(*self);
@@ -72,6 +190,34 @@ impl Hex {
     }
 }
 
+// An axial pair whose `s = -q - r` doesn't fit in an i32
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxialOutOfRange;
+
+impl fmt::Display for AxialOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hex coordinates out of range")
+    }
+}
+
+impl std::error::Error for AxialOutOfRange {}
+
+// Checked, unlike `from_axial`, for pairs read from outside
+impl TryFrom<(i32, i32)> for Hex {
+    type Error = AxialOutOfRange;
+
+    fn try_from((q, r): (i32, i32)) -> Result<Self, Self::Error> {
+        let s = i32::try_from(-(q as i64) - r as i64).map_err(|_| AxialOutOfRange)?;
+        Ok(Hex { q, r, s })
+    }
+}
+
+impl From<Hex> for (i32, i32) {
+    fn from(hex: Hex) -> Self {
+        (hex.q, hex.r)
+    }
+}
+
 impl Add<Hex> for Hex {
     type Output = Hex;
 
@@ -126,7 +272,80 @@ impl MulAssign<i32> for Hex {
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<Hex> for FractionalHex {
+    fn from(hex: Hex) -> Self {
+        FractionalHex {
+            q: hex.q as f32,
+            r: hex.r as f32,
+            s: hex.s as f32,
+        }
+    }
+}
+
+impl Add<FractionalHex> for FractionalHex {
+    type Output = FractionalHex;
+
+    fn add(self, _rhs: FractionalHex) -> FractionalHex {
+        FractionalHex {
+            q: self.q + _rhs.q,
+            r: self.r + _rhs.r,
+            s: self.s + _rhs.s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexAxis {
+    Q,
+    R,
+    S,
+}
+
+// A point in cube coordinates that isn't necessarily a hex centre
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FractionalHex {
+    pub q: f32,
+    pub r: f32,
+    pub s: f32,
+}
+
+impl FractionalHex {
+    pub fn from_axial(q: f32, r: f32) -> Self {
+        FractionalHex { q, r, s: -q - r }
+    }
+
+    pub fn lerp(self, other: FractionalHex, t: f32) -> Self {
+        FractionalHex {
+            q: self.q + (other.q - self.q) * t,
+            r: self.r + (other.r - self.r) * t,
+            s: self.s + (other.s - self.s) * t,
+        }
+    }
+
+    // Rounds each coordinate, then fixes up the one that moved the most so
+    // that q + r + s stays 0
+    pub fn round(self) -> Hex {
+        let mut q = self.q.round();
+        let mut r = self.r.round();
+        let mut s = self.s.round();
+
+        let dq = (q - self.q).abs();
+        let dr = (r - self.r).abs();
+        let ds = (s - self.s).abs();
+
+        if dq > dr && dq > ds {
+            q = -r - s;
+        } else if dr > ds {
+            r = -q - s;
+        } else {
+            s = -q - r;
+        }
+
+        Hex::from_cube(q as i32, r as i32, s as i32)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Orientation {
     pub f: Mat2,
     pub f_inv: Mat2,
@@ -139,6 +358,12 @@ impl Orientation {
         f_inv: Mat2::from_cols_array(&[SQRT_3 / 3.0, 0.0, -1.0 / 3.0, 2.0 / 3.0]),
         start_angle: 0.5 * std::f64::consts::PI,
     };
+
+    pub const LAYOUT_FLAT: Orientation = Orientation {
+        f: Mat2::from_cols_array(&[3.0 / 2.0, SQRT_3 / 2.0, 0.0, SQRT_3]),
+        f_inv: Mat2::from_cols_array(&[2.0 / 3.0, -1.0 / 3.0, 0.0, SQRT_3 / 3.0]),
+        start_angle: 0.0,
+    };
 }
 
 #[derive(Debug, Clone)]
@@ -156,9 +381,36 @@ impl Layout {
 
         mat.f * vec2(hex.q as f32, hex.r as f32) * size + origin
     }
+
+    pub fn pixel_to_hex_fractional(&self, pixel: Vec2) -> FractionalHex {
+        let mat = &self.orientation;
+        let pt = (pixel - self.origin) / self.size;
+        let qr = mat.f_inv * pt;
+
+        FractionalHex::from_axial(qr.x, qr.y)
+    }
+
+    pub fn pixel_to_hex(&self, pixel: Vec2) -> Hex {
+        self.pixel_to_hex_fractional(pixel).round()
+    }
+
+    pub fn hex_corner_offset(&self, corner: usize) -> Vec2 {
+        let angle = self.orientation.start_angle + corner as f64 * std::f64::consts::PI / 3.0;
+
+        vec2(
+            self.size.x * angle.cos() as f32,
+            self.size.y * angle.sin() as f32,
+        )
+    }
+
+    pub fn polygon_corners(&self, hex: Hex) -> [Vec2; 6] {
+        let center = self.hex_to_pixel(hex);
+        std::array::from_fn(|corner| center + self.hex_corner_offset(corner))
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum HexDirection {
     E,
     SE,
@@ -218,8 +470,8 @@ impl HexDirection {
 
     pub fn get_dir_from_to(from: Hex, to: Hex) -> Self {
         let diff = to - from;
-        for dir in 0..6 {
-            if diff == DIR[dir] {
+        for (dir, delta) in DIR.iter().enumerate() {
+            if diff == *delta {
                 return HexDirection::from_usize(dir);
             }
         }
@@ -237,3 +489,121 @@ impl HexDirection {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_roundtrip() {
+        let hex = Hex::from_axial(3, -5);
+        let json = serde_json::to_string(&hex).unwrap();
+        assert_eq!(json, "[3,-5]");
+        assert_eq!(serde_json::from_str::<Hex>(&json).unwrap(), hex);
+
+        let dir = serde_json::to_string(&HexDirection::NW).unwrap();
+        assert_eq!(
+            serde_json::from_str::<HexDirection>(&dir).unwrap(),
+            HexDirection::NW
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_rejects_cube_coordinates() {
+        assert!(serde_json::from_str::<Hex>(r#"{"q":1,"r":0,"s":0}"#).is_err());
+        assert!(serde_json::from_str::<Hex>("[1,0,0]").is_err());
+        assert!(serde_json::from_str::<Hex>("[1]").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_rejects_overflowing_s() {
+        assert!(serde_json::from_str::<Hex>("[-2147483648,0]").is_err());
+        assert!(serde_json::from_str::<Hex>("[2147483647,2]").is_err());
+
+        // Right at the ends, s still fits
+        let hex = serde_json::from_str::<Hex>("[-2147483648,1]").unwrap();
+        assert_eq!(hex.s, i32::MAX);
+    }
+
+    fn samples() -> Vec<Hex> {
+        Hex::from_axial(2, -1).spiral(3)
+    }
+
+    #[test]
+    fn six_rotations_are_the_identity() {
+        let center = Hex::from_axial(1, 2);
+        for hex in samples() {
+            assert_eq!(hex.rotate(center, 6), hex);
+            assert_eq!(hex.rotate(center, -6), hex);
+            assert_eq!(hex.rotate(center, 1).rotate(center, -1), hex);
+        }
+
+        // One step turns each direction into the next one
+        for i in 0..6 {
+            assert_eq!(DIR[i].rotate(Hex::from_axial(0, 0), 1), DIR[(i + 1) % 6]);
+        }
+    }
+
+    #[test]
+    fn reflecting_twice_is_the_identity() {
+        let center = Hex::from_axial(-1, 3);
+        for axis in [HexAxis::Q, HexAxis::R, HexAxis::S] {
+            for hex in samples() {
+                assert_eq!(hex.reflect(center, axis).reflect(center, axis), hex);
+            }
+        }
+
+        // On Q, the hexes with r == s stay put and q is kept
+        let on_line = center + Hex::from_cube(2, -1, -1);
+        assert_eq!(on_line.reflect(center, HexAxis::Q), on_line);
+        let off_line = center + Hex::from_cube(1, -1, 0);
+        assert_eq!(
+            off_line.reflect(center, HexAxis::Q),
+            center + Hex::from_cube(1, 0, -1)
+        );
+    }
+
+    #[test]
+    fn lines_join_their_endpoints() {
+        let a = Hex::from_axial(-2, 1);
+        for b in samples() {
+            let line = a.line_to(b);
+            assert_eq!(line.len() as i32, a.distance(b) + 1);
+            assert_eq!(line.first(), Some(&a));
+            assert_eq!(line.last(), Some(&b));
+
+            for pair in line.windows(2) {
+                assert_eq!(pair[0].distance(pair[1]), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn range_counts() {
+        let center = Hex::from_axial(4, -7);
+        for n in 0..6 {
+            let range = center.range(n);
+            assert_eq!(range.len() as i32, 3 * n * (n + 1) + 1);
+            assert!(range.iter().all(|hex| hex.distance(center) <= n));
+            assert_eq!(center.spiral(n).len(), range.len());
+        }
+    }
+
+    #[test]
+    fn pixel_roundtrip() {
+        for orientation in [Orientation::LAYOUT_POINTY, Orientation::LAYOUT_FLAT] {
+            let layout = Layout {
+                orientation,
+                size: vec2(20.0, 24.0),
+                origin: vec2(300.0, -50.0),
+            };
+
+            for hex in samples() {
+                assert_eq!(layout.pixel_to_hex(layout.hex_to_pixel(hex)), hex);
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::core::game::*;
+use crate::core::hex::*;
+
+// Everything a player can do while playing. `Game::update` only sees these,
+// so the keyboard, a script or the solver can all drive it the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Move(HexDirection),
+    Zoom(f32),
+    // Turns the board by 60 degree steps, counter-clockwise
+    Rotate(i32),
+    ToggleFlat,
+    Undo,
+    Restart,
+}
+
+pub trait InputSource {
+    // Actions for this frame, in order
+    fn actions(&mut self, game: &Game) -> Vec<Action>;
+}
+
+// Controls for watching a replay. They drive `Playback` and not the game,
+// so they're kept apart from `Action`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackControl {
+    TogglePause,
+    Faster,
+    Slower,
+    // One move ahead while paused
+    Step,
+    Rewind,
+}
+
+pub trait PlaybackInput {
+    // Controls for this frame, in order
+    fn controls(&mut self) -> Vec<PlaybackControl>;
+}
+
+// Moving through menus, e.g. the pause menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuControl {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+pub trait MenuInput {
+    // Controls for this frame, in order
+    fn menu_controls(&mut self) -> Vec<MenuControl>;
+}
+
+// The keyboard, and clicking a hex to roll onto it. macroquad turns taps into
+// mouse clicks, so this covers touch screens too.
+#[derive(Debug, Default)]
+pub struct KeyboardMouse;
+
+impl InputSource for KeyboardMouse {
+    fn actions(&mut self, game: &Game) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let hex = game.screen_to_hex(mouse_position().into());
+            if let Some(dir) = game.direction_towards(hex) {
+                actions.push(Action::Move(dir));
+            }
+        }
+
+        let Some(key) = get_last_key_pressed() else {
+            return actions;
+        };
+
+        // The move keys sit around S on the keyboard, so each one points the
+        // same way on screen however the board is turned
+        let action = match key {
+            KeyCode::D => Action::Move(game.screen_direction(0)),
+            KeyCode::E => Action::Move(game.screen_direction(1)),
+            KeyCode::W => Action::Move(game.screen_direction(2)),
+            KeyCode::A => Action::Move(game.screen_direction(3)),
+            KeyCode::Z => Action::Move(game.screen_direction(4)),
+            KeyCode::X => Action::Move(game.screen_direction(5)),
+            KeyCode::Up => Action::Zoom(0.5),
+            KeyCode::Down => Action::Zoom(-0.5),
+            KeyCode::Left => Action::Rotate(1),
+            KeyCode::Right => Action::Rotate(-1),
+            KeyCode::F => Action::ToggleFlat,
+            KeyCode::U => Action::Undo,
+            KeyCode::R => Action::Restart,
+            _ => return actions,
+        };
+        actions.push(action);
+
+        actions
+    }
+}
+
+impl PlaybackInput for KeyboardMouse {
+    fn controls(&mut self) -> Vec<PlaybackControl> {
+        [
+            (KeyCode::Space, PlaybackControl::TogglePause),
+            (KeyCode::Up, PlaybackControl::Faster),
+            (KeyCode::Down, PlaybackControl::Slower),
+            (KeyCode::N, PlaybackControl::Step),
+            (KeyCode::Backspace, PlaybackControl::Rewind),
+        ]
+        .into_iter()
+        .filter(|(key, _)| is_key_pressed(*key))
+        .map(|(_, control)| control)
+        .collect()
+    }
+}
+
+// The mouse is left to the menus themselves, they know where their items are
+impl MenuInput for KeyboardMouse {
+    fn menu_controls(&mut self) -> Vec<MenuControl> {
+        [
+            (KeyCode::Up, MenuControl::Up),
+            (KeyCode::Down, MenuControl::Down),
+            (KeyCode::Left, MenuControl::Left),
+            (KeyCode::Right, MenuControl::Right),
+            (KeyCode::Enter, MenuControl::Select),
+            (KeyCode::Space, MenuControl::Select),
+            (KeyCode::Escape, MenuControl::Back),
+        ]
+        .into_iter()
+        .filter(|(key, _)| is_key_pressed(*key))
+        .map(|(_, control)| control)
+        .collect()
+    }
+}
+
+// How far the stick has to be pushed to roll the block, and let back before
+// it rolls again
+#[cfg(feature = "gamepad")]
+const STICK_PUSH: f32 = 0.6;
+#[cfg(feature = "gamepad")]
+const STICK_RELEASE: f32 = 0.3;
+
+// Any connected gamepad, read through gilrs since macroquad doesn't. The
+// left stick rolls the block the way it points on screen, like the move
+// keys. `Gamepad::poll` reads it once a frame. Without the `gamepad`
+// feature, or without a gamepad, nothing is ever pressed.
+#[derive(Debug, Default)]
+pub struct Gamepad {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+    #[cfg(feature = "gamepad")]
+    stick: (f32, f32),
+    #[cfg(feature = "gamepad")]
+    is_stick_held: bool,
+    // Read by the last poll, as in `Game::screen_direction`
+    moves: Vec<usize>,
+    actions: Vec<Action>,
+    controls: Vec<PlaybackControl>,
+    menu: Vec<MenuControl>,
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        Gamepad {
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new().ok(),
+            ..Default::default()
+        }
+    }
+
+    pub fn poll(&mut self) {
+        self.moves.clear();
+        self.actions.clear();
+        self.controls.clear();
+        self.menu.clear();
+
+        #[cfg(feature = "gamepad")]
+        {
+            use gilrs::{Axis, Button, EventType};
+
+            let Some(gilrs) = self.gilrs.as_mut() else {
+                return;
+            };
+
+            while let Some(event) = gilrs.next_event() {
+                match event.event {
+                    EventType::AxisChanged(Axis::LeftStickX, x, _) => self.stick.0 = x,
+                    EventType::AxisChanged(Axis::LeftStickY, y, _) => self.stick.1 = y,
+                    EventType::ButtonPressed(button, _) => {
+                        let action = match button {
+                            Button::West => Some(Action::Undo),
+                            Button::North => Some(Action::ToggleFlat),
+                            Button::Select => Some(Action::Restart),
+                            Button::LeftTrigger => Some(Action::Rotate(1)),
+                            Button::RightTrigger => Some(Action::Rotate(-1)),
+                            Button::DPadUp => Some(Action::Zoom(0.5)),
+                            Button::DPadDown => Some(Action::Zoom(-0.5)),
+                            _ => None,
+                        };
+                        self.actions.extend(action);
+
+                        let control = match button {
+                            Button::South => Some(PlaybackControl::TogglePause),
+                            Button::DPadUp => Some(PlaybackControl::Faster),
+                            Button::DPadDown => Some(PlaybackControl::Slower),
+                            Button::RightTrigger => Some(PlaybackControl::Step),
+                            Button::Select => Some(PlaybackControl::Rewind),
+                            _ => None,
+                        };
+                        self.controls.extend(control);
+
+                        let control = match button {
+                            Button::DPadUp => Some(MenuControl::Up),
+                            Button::DPadDown => Some(MenuControl::Down),
+                            Button::DPadLeft => Some(MenuControl::Left),
+                            Button::DPadRight => Some(MenuControl::Right),
+                            Button::South => Some(MenuControl::Select),
+                            Button::East | Button::Start => Some(MenuControl::Back),
+                            _ => None,
+                        };
+                        self.menu.extend(control);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Up is positive, so 0 is right and the steps go counter-clockwise
+            let (x, y) = self.stick;
+            let length = x.hypot(y);
+            if length < STICK_RELEASE {
+                self.is_stick_held = false;
+            } else if length > STICK_PUSH && !self.is_stick_held {
+                self.is_stick_held = true;
+                let step = (y.atan2(x).to_degrees() / 60.0).round().rem_euclid(6.0);
+                self.moves.push(step as usize);
+            }
+        }
+    }
+}
+
+impl InputSource for Gamepad {
+    fn actions(&mut self, game: &Game) -> Vec<Action> {
+        let moves = self
+            .moves
+            .drain(..)
+            .map(|step| Action::Move(game.screen_direction(step)));
+        moves.chain(self.actions.drain(..)).collect()
+    }
+}
+
+impl PlaybackInput for Gamepad {
+    fn controls(&mut self) -> Vec<PlaybackControl> {
+        std::mem::take(&mut self.controls)
+    }
+}
+
+impl MenuInput for Gamepad {
+    fn menu_controls(&mut self) -> Vec<MenuControl> {
+        std::mem::take(&mut self.menu)
+    }
+}
+
+// Plays a fixed list of actions, one per frame, e.g. from a test or a
+// parsed move string
+#[derive(Debug, Default)]
+pub struct Scripted {
+    pub actions: VecDeque<Action>,
+}
+
+impl Scripted {
+    pub fn new(actions: impl IntoIterator<Item = Action>) -> Self {
+        Scripted {
+            actions: actions.into_iter().collect(),
+        }
+    }
+
+    pub fn from_moves(moves: &[HexDirection]) -> Self {
+        Scripted::new(moves.iter().map(|dir| Action::Move(*dir)))
+    }
+}
+
+impl InputSource for Scripted {
+    fn actions(&mut self, _game: &Game) -> Vec<Action> {
+        self.actions.pop_front().into_iter().collect()
+    }
+}
+
+// Plays the rest of an optimal solution from wherever the block is, one move
+// every `interval` seconds
+#[derive(Debug)]
+pub struct Solver {
+    pub interval: f64,
+    last_move: f64,
+}
+
+impl Solver {
+    pub fn new(interval: f64) -> Self {
+        Solver {
+            interval,
+            last_move: 0.0,
+        }
+    }
+}
+
+impl InputSource for Solver {
+    fn actions(&mut self, game: &Game) -> Vec<Action> {
+        if get_time() - self.last_move < self.interval {
+            return Vec::new();
+        }
+        self.last_move = get_time();
+
+        let moves = game.map.solve_from(game.player_state).moves;
+        moves
+            .and_then(|moves| moves.first().copied())
+            .map(Action::Move)
+            .into_iter()
+            .collect()
+    }
+}
@@ -0,0 +1,478 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::core::grid::HexGrid;
+use crate::core::hex::*;
+use crate::core::map::HexMap;
+
+// .hexl level files
+//
+//     name: First Steps
+//     author: sifmuna00
+//     par: 7
+//     length: 2
+//     origin: 0,2
+//     ---
+//      A * * * *
+//     . * * X *
+//
+// The header is a list of `key: value` lines, `#` starts a comment line.
+// Everything after `---` is the grid: one cell per character, spaces are
+// only there for readability. Rows and columns are offset coordinates
+// starting at `origin`, so `. * A` on the first row puts the start at
+// `(origin.0 + 2, origin.1)`.
+//
+// `link: 3,4 -> 5,6` lines are part of the format, but the game can't play
+// them yet, so loading a level that has any is an error for now.
+
+pub const HEADER_END: &str = "---";
+
+pub const TILE_EMPTY: char = '.';
+pub const TILE_FLOOR: char = '*';
+pub const TILE_START: char = 'A';
+pub const TILE_GOAL: char = 'X';
+
+// Furthest a column or row can be from 0,0, far beyond any real level but
+// small enough that the coordinate math can't overflow
+pub const MAX_COORD: i32 = 1 << 16;
+
+// The only block the game can play so far, other lengths are rejected on load
+pub const BLOCK_LENGTH: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub author: String,
+    pub par: Option<u32>,
+    pub length: u32,
+    pub links: Vec<(Hex, Hex)>,
+    pub map: HexMap,
+}
+
+impl Level {
+    pub fn from_map(map: HexMap) -> Self {
+        Level {
+            name: String::new(),
+            author: String::new(),
+            par: None,
+            length: BLOCK_LENGTH,
+            links: Vec::new(),
+            map,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    MissingSeparator,
+    MissingValue,
+    UnknownKey(String),
+    DuplicateKey(String),
+    InvalidNumber(String),
+    InvalidCoord(String),
+    InvalidLink(String),
+    UnsupportedLength(u32),
+    UnsupportedLink,
+    CoordOutOfRange(i32, i32),
+    MapTooLarge,
+    UnknownTile(char),
+    MissingStart,
+    MissingGoal,
+    DuplicateStart,
+    DuplicateGoal,
+    InvalidMove(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(line: usize, column: usize, kind: ParseErrorKind) -> Self {
+        ParseError { line, column, kind }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::MissingSeparator => {
+                write!(f, "expected `{}` before the grid", HEADER_END)
+            }
+            ParseErrorKind::MissingValue => write!(f, "expected `key: value`"),
+            ParseErrorKind::UnknownKey(key) => write!(f, "unknown key `{}`", key),
+            ParseErrorKind::DuplicateKey(key) => write!(f, "duplicate key `{}`", key),
+            ParseErrorKind::InvalidNumber(value) => write!(f, "invalid number `{}`", value),
+            ParseErrorKind::InvalidCoord(value) => {
+                write!(f, "invalid coordinate `{}`, expected `col,row`", value)
+            }
+            ParseErrorKind::InvalidLink(value) => {
+                write!(f, "invalid link `{}`, expected `col,row -> col,row`", value)
+            }
+            ParseErrorKind::UnsupportedLength(length) => write!(
+                f,
+                "block length {} isn't supported, only {}",
+                length, BLOCK_LENGTH
+            ),
+            ParseErrorKind::UnsupportedLink => write!(f, "links aren't supported yet"),
+            ParseErrorKind::CoordOutOfRange(col, row) => write!(
+                f,
+                "coordinate `{},{}` is more than {} away from 0,0",
+                col, row, MAX_COORD
+            ),
+            ParseErrorKind::MapTooLarge => write!(
+                f,
+                "tiles are spread over more than {} cells",
+                crate::core::grid::MAX_CELLS
+            ),
+            ParseErrorKind::UnknownTile(c) => write!(f, "unknown tile `{}`", c),
+            ParseErrorKind::MissingStart => write!(f, "no start tile `{}`", TILE_START),
+            ParseErrorKind::MissingGoal => write!(f, "no goal tile `{}`", TILE_GOAL),
+            ParseErrorKind::DuplicateStart => write!(f, "second start tile `{}`", TILE_START),
+            ParseErrorKind::DuplicateGoal => write!(f, "second goal tile `{}`", TILE_GOAL),
+            ParseErrorKind::InvalidMove(e) => write!(f, "invalid move: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn parse_number(value: &str) -> Result<u32, ParseErrorKind> {
+    value
+        .parse()
+        .map_err(|_| ParseErrorKind::InvalidNumber(value.to_owned()))
+}
+
+fn parse_coord(value: &str) -> Result<(i32, i32), ParseErrorKind> {
+    let invalid = || ParseErrorKind::InvalidCoord(value.to_owned());
+    let (col, row) = value.split_once(',').ok_or_else(invalid)?;
+    let col = col.trim().parse().map_err(|_| invalid())?;
+    let row = row.trim().parse().map_err(|_| invalid())?;
+
+    check_coord((col, row))
+}
+
+fn check_coord((col, row): (i32, i32)) -> Result<(i32, i32), ParseErrorKind> {
+    if col.unsigned_abs() > MAX_COORD as u32 || row.unsigned_abs() > MAX_COORD as u32 {
+        return Err(ParseErrorKind::CoordOutOfRange(col, row));
+    }
+
+    Ok((col, row))
+}
+
+fn parse_link(value: &str) -> Result<(Hex, Hex), ParseErrorKind> {
+    let (from, to) = value
+        .split_once("->")
+        .ok_or_else(|| ParseErrorKind::InvalidLink(value.to_owned()))?;
+
+    Ok((
+        Hex::from_offset(parse_coord(from.trim())?),
+        Hex::from_offset(parse_coord(to.trim())?),
+    ))
+}
+
+impl FromStr for Level {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut level = Level::from_map(HexMap {
+            hexmap: HexGrid::new(),
+            start: Hex::from_axial(0, 0),
+            goal: Hex::from_axial(0, 0),
+        });
+        let mut origin = (0, 0);
+        let mut seen_keys: Vec<&str> = Vec::new();
+
+        let mut lines = src.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let mut last_line = 0;
+
+        // Header
+        loop {
+            let Some((line_no, line)) = lines.next() else {
+                return Err(ParseError::new(
+                    last_line + 1,
+                    1,
+                    ParseErrorKind::MissingSeparator,
+                ));
+            };
+            last_line = line_no;
+
+            let trimmed = line.trim();
+            if trimmed == HEADER_END {
+                break;
+            }
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let column = line.len() - line.trim_start().len() + 1;
+            let Some((key, value)) = trimmed.split_once(':') else {
+                return Err(ParseError::new(
+                    line_no,
+                    column,
+                    ParseErrorKind::MissingValue,
+                ));
+            };
+            let key = key.trim();
+            let value = value.trim();
+            let colon = line.find(':').unwrap();
+            let rest = &line[colon + 1..];
+            let value_column = colon + 2 + rest.len() - rest.trim_start().len();
+            let err = |kind| ParseError::new(line_no, value_column, kind);
+
+            if key != "link" {
+                if seen_keys.contains(&key) {
+                    return Err(ParseError::new(
+                        line_no,
+                        column,
+                        ParseErrorKind::DuplicateKey(key.to_owned()),
+                    ));
+                }
+                seen_keys.push(key);
+            }
+
+            match key {
+                "name" => level.name = value.to_owned(),
+                "author" => level.author = value.to_owned(),
+                "par" => level.par = Some(parse_number(value).map_err(err)?),
+                "length" => {
+                    let length = parse_number(value).map_err(err)?;
+                    if length != BLOCK_LENGTH {
+                        return Err(err(ParseErrorKind::UnsupportedLength(length)));
+                    }
+                    level.length = length;
+                }
+                "origin" => origin = parse_coord(value).map_err(err)?,
+                "link" => {
+                    // Checked so a bad link still says what's wrong with it
+                    parse_link(value).map_err(err)?;
+                    return Err(err(ParseErrorKind::UnsupportedLink));
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        line_no,
+                        column,
+                        ParseErrorKind::UnknownKey(key.to_owned()),
+                    ))
+                }
+            }
+        }
+
+        // Grid
+        let mut start = None;
+        let mut goal = None;
+        let mut row = origin.1;
+
+        for (line_no, line) in lines {
+            last_line = line_no;
+            let mut col = origin.0;
+
+            for (i, c) in line.chars().enumerate() {
+                if c.is_whitespace() {
+                    continue;
+                }
+
+                let err = |kind| ParseError::new(line_no, i + 1, kind);
+                let hex = Hex::from_offset(check_coord((col, row)).map_err(err)?);
+                col = col.saturating_add(1);
+
+                match c {
+                    TILE_EMPTY => continue,
+                    TILE_FLOOR => {}
+                    TILE_START => {
+                        if start.is_some() {
+                            return Err(err(ParseErrorKind::DuplicateStart));
+                        }
+                        start = Some(hex);
+                    }
+                    TILE_GOAL => {
+                        if goal.is_some() {
+                            return Err(err(ParseErrorKind::DuplicateGoal));
+                        }
+                        goal = Some(hex);
+                    }
+                    _ => return Err(err(ParseErrorKind::UnknownTile(c))),
+                }
+
+                level
+                    .map
+                    .hexmap
+                    .insert(hex)
+                    .map_err(|_| err(ParseErrorKind::MapTooLarge))?;
+            }
+
+            row = row.saturating_add(1);
+        }
+
+        let end = ParseError::new(last_line + 1, 1, ParseErrorKind::MissingStart);
+        level.map.start = start.ok_or(end.clone())?;
+        level.map.goal = goal.ok_or(ParseError {
+            kind: ParseErrorKind::MissingGoal,
+            ..end
+        })?;
+
+        Ok(level)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offsets = self
+            .map
+            .hexmap
+            .iter()
+            .map(|hex| hex.to_offset())
+            .collect::<Vec<_>>();
+
+        let min_col = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+        let min_row = offsets.iter().map(|o| o.1).min().unwrap_or(0);
+
+        if !self.name.is_empty() {
+            writeln!(f, "name: {}", self.name)?;
+        }
+        if !self.author.is_empty() {
+            writeln!(f, "author: {}", self.author)?;
+        }
+        if let Some(par) = self.par {
+            writeln!(f, "par: {}", par)?;
+        }
+        writeln!(f, "length: {}", self.length)?;
+        writeln!(f, "origin: {},{}", min_col, min_row)?;
+        for (from, to) in &self.links {
+            let (from, to) = (from.to_offset(), to.to_offset());
+            writeln!(f, "link: {},{} -> {},{}", from.0, from.1, to.0, to.1)?;
+        }
+        writeln!(f, "{}", HEADER_END)?;
+
+        write!(f, "{}", self.map.to_ascii(None))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::map::load_map;
+
+    fn error(src: &str) -> (usize, usize, ParseErrorKind) {
+        let e = src.parse::<Level>().unwrap_err();
+        (e.line, e.column, e.kind)
+    }
+
+    #[test]
+    fn write_then_parse_is_lossless() {
+        let mut level = Level::from_map(load_map(HexMap::PREMADE_MAP_0).unwrap());
+        level.name = String::from("First Steps");
+        level.author = String::from("sifmuna00");
+        level.par = Some(7);
+
+        let parsed = level.to_string().parse::<Level>().unwrap();
+        assert_eq!(parsed, level);
+    }
+
+    #[test]
+    fn roundtrip_keeps_odd_and_negative_origins() {
+        let mut map = load_map(HexMap::PREMADE_MAP_1).unwrap();
+        let shift = Hex::from_offset((-7, -3));
+        map.hexmap = HexGrid::try_from_iter(map.hexmap.iter().map(|hex| hex + shift)).unwrap();
+        map.start += shift;
+        map.goal += shift;
+        let level = Level::from_map(map);
+
+        let parsed = level.to_string().parse::<Level>().unwrap();
+        assert_eq!(parsed, level);
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(error("name: x\n"), (2, 1, ParseErrorKind::MissingSeparator));
+        assert_eq!(
+            error("  name x\n---\n"),
+            (1, 3, ParseErrorKind::MissingValue)
+        );
+        assert_eq!(
+            error("colour: red\n---\n"),
+            (1, 1, ParseErrorKind::UnknownKey(String::from("colour")))
+        );
+        assert_eq!(
+            error("name: a\nname: b\n---\n"),
+            (2, 1, ParseErrorKind::DuplicateKey(String::from("name")))
+        );
+        assert_eq!(
+            error("par: many\n---\n"),
+            (1, 6, ParseErrorKind::InvalidNumber(String::from("many")))
+        );
+        assert_eq!(
+            error("length: 3\n---\n A X\n"),
+            (1, 9, ParseErrorKind::UnsupportedLength(3))
+        );
+        assert_eq!(
+            error("origin: 1\n---\n"),
+            (1, 9, ParseErrorKind::InvalidCoord(String::from("1")))
+        );
+        assert_eq!(
+            error("link: 1,2\n---\n"),
+            (1, 7, ParseErrorKind::InvalidLink(String::from("1,2")))
+        );
+        assert_eq!(
+            error("link: 3,4 -> 5,6\n---\n A X\n"),
+            (1, 7, ParseErrorKind::UnsupportedLink)
+        );
+    }
+
+    #[test]
+    fn coordinates_out_of_range() {
+        assert_eq!(
+            error("origin: 2147483647,0\n---\n A * X\n"),
+            (1, 9, ParseErrorKind::CoordOutOfRange(i32::MAX, 0))
+        );
+        assert_eq!(
+            error("origin: -2147483648,0\n---\n A * X\n"),
+            (1, 9, ParseErrorKind::CoordOutOfRange(i32::MIN, 0))
+        );
+        assert_eq!(
+            error("link: 0,0 -> 0,99999\n---\n A * X\n"),
+            (1, 7, ParseErrorKind::CoordOutOfRange(0, 99999))
+        );
+        // The origin is fine but the grid runs past the edge
+        assert_eq!(
+            error(&format!("origin: {},0\n---\n A * X\n", MAX_COORD)),
+            (3, 4, ParseErrorKind::CoordOutOfRange(MAX_COORD + 1, 0))
+        );
+    }
+
+    #[test]
+    fn tiles_far_apart() {
+        let src = format!("origin: 0,0\n---\n A X\n{}*\n", "\n".repeat(9000));
+        let e = src.parse::<Level>().unwrap_err();
+        assert_eq!((e.line, e.kind), (9004, ParseErrorKind::MapTooLarge));
+    }
+
+    #[test]
+    fn grid_errors() {
+        assert_eq!(
+            error("---\n A ? X\n"),
+            (2, 4, ParseErrorKind::UnknownTile('?'))
+        );
+        assert_eq!(error("---\n * X\n"), (3, 1, ParseErrorKind::MissingStart));
+        assert_eq!(error("---\n A *\n"), (3, 1, ParseErrorKind::MissingGoal));
+        assert_eq!(
+            error("---\n A A X\n"),
+            (2, 4, ParseErrorKind::DuplicateStart)
+        );
+        assert_eq!(
+            error("---\n A X X\n"),
+            (2, 6, ParseErrorKind::DuplicateGoal)
+        );
+    }
+}
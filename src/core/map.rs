@@ -1,15 +1,21 @@
 use std::collections::HashMap;
+use std::fmt;
 
-use macroquad::prelude::rand;
 use petgraph::algo::astar;
 use petgraph::graph::NodeIndex;
 use petgraph::Graph;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::core::game::PlayerState;
+use crate::core::grid::HexGrid;
 use crate::core::hex::*;
+use crate::core::rng::Rng;
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HexMap {
-    pub hexmap: HashMap<Hex, bool>,
+    pub hexmap: HexGrid,
     pub start: Hex,
     pub goal: Hex,
 }
@@ -23,39 +29,27 @@ impl HexMap {
 
         let mut idx = HashMap::new();
 
-        for head in self.hexmap.keys() {
+        for head in self.hexmap.iter() {
             // Standing
             for dir in HEX_DIRECTIONS {
-                let cur = PlayerState::Standing(*head);
+                let cur = PlayerState::Standing(head);
 
-                let icur = if !idx.contains_key(&cur) {
-                    let i = g.add_node(cur);
-                    idx.insert(cur, i);
-                    i
-                } else {
-                    idx[&cur]
-                };
+                let icur = *idx.entry(cur).or_insert_with(|| g.add_node(cur));
 
-                if *head == hex_start {
+                if head == hex_start {
                     start = icur;
                 }
 
-                if *head == self.goal {
+                if head == self.goal {
                     goal = icur;
                 }
 
-                let next = cur.next_state_in_map(dir, &self.hexmap);
-                if next == PlayerState::Dead {
+                let next = cur.next_state_in_map(dir, self);
+                if next.is_dead() {
                     continue;
                 }
 
-                let inext = if !idx.contains_key(&next) {
-                    let i = g.add_node(next);
-                    idx.insert(next, i);
-                    i
-                } else {
-                    idx[&next]
-                };
+                let inext = *idx.entry(next).or_insert_with(|| g.add_node(next));
 
                 g.update_edge(icur, inext, 1);
             }
@@ -64,33 +58,21 @@ impl HexMap {
             for dir in HEX_DIRECTIONS {
                 let tail = head.neighbor(dir);
 
-                if !self.hexmap.contains_key(&tail) {
+                if !self.is_in_map(tail) {
                     continue;
                 }
 
-                let cur = PlayerState::Flat(*head, tail);
+                let cur = PlayerState::Flat(head, tail);
 
-                let icur = if !idx.contains_key(&cur) {
-                    let i = g.add_node(cur);
-                    idx.insert(cur, i);
-                    i
-                } else {
-                    idx[&cur]
-                };
+                let icur = *idx.entry(cur).or_insert_with(|| g.add_node(cur));
 
                 for dir2 in HEX_DIRECTIONS {
-                    let next = cur.next_state_in_map(dir2, &self.hexmap);
-                    if next == PlayerState::Dead {
+                    let next = cur.next_state_in_map(dir2, self);
+                    if next.is_dead() {
                         continue;
                     }
 
-                    let inext = if !idx.contains_key(&next) {
-                        let i = g.add_node(next);
-                        idx.insert(next, i);
-                        i
-                    } else {
-                        idx[&next]
-                    };
+                    let inext = *idx.entry(next).or_insert_with(|| g.add_node(next));
 
                     g.update_edge(icur, inext, 1);
                 }
@@ -115,37 +97,16 @@ impl HexMap {
         }
     }
 
-    fn is_in_map(&self, hex: Hex) -> bool {
-        self.hexmap.contains_key(&hex)
+    pub fn is_in_map(&self, hex: Hex) -> bool {
+        self.hexmap.contains(hex)
     }
 
-    pub fn dump_map(&self, radius: i32) {
-        let s = radius;
-        for r in -s..=s {
-            for _ in 0..r {
-                print!(" ");
-            }
-            for q in -s..=s {
-                let pos = Hex::from_axial(q, r);
-                if self.is_in_map(pos) {
-                    if pos == self.goal {
-                        print!("X ");
-                    } else if pos == self.start {
-                        print!("A ");
-                    } else {
-                        print!("* ");
-                    }
-                } else {
-                    print!("- ");
-                }
-            }
-            println!();
-        }
-        println!();
+    pub fn gen() -> Self {
+        HexMap::gen_with(&mut Rng::new(Rng::random_seed()))
     }
 
-    pub fn gen() -> Self {
-        let mut hexmap = HashMap::new();
+    pub fn gen_with(rng: &mut Rng) -> Self {
+        let mut tiles = Vec::new();
         let start = Hex::from_axial(0, 0);
 
         let mut last_hex = start;
@@ -153,32 +114,36 @@ impl HexMap {
         let mut cnt = 20;
         while cnt > 0 {
             cnt -= 1;
-            let mut vdir = Vec::new();
-            vdir.push(DIR[HexDirection::to_usize(HexDirection::SW)]);
-            vdir.push(DIR[HexDirection::to_usize(HexDirection::SE)]);
-            vdir.push(DIR[HexDirection::to_usize(HexDirection::E)]);
-            vdir.push(DIR[HexDirection::to_usize(HexDirection::W)]);
-
-            if rand::gen_range(0, 3) == 0 {
+            let vdir = [
+                DIR[HexDirection::to_usize(HexDirection::SW)],
+                DIR[HexDirection::to_usize(HexDirection::SE)],
+                DIR[HexDirection::to_usize(HexDirection::E)],
+                DIR[HexDirection::to_usize(HexDirection::W)],
+            ];
+
+            if rng.gen_range(0, 3) == 0 {
                 for _ in 0..3 {
-                    let p_hex = last_hex + vdir[rand::gen_range(0, vdir.len())];
-                    hexmap.insert(p_hex, true);
+                    let p_hex = last_hex + vdir[rng.gen_range(0, vdir.len())];
+                    tiles.push(p_hex);
                 }
             }
 
-            let next_hex = last_hex + vdir[rand::gen_range(0, vdir.len())];
+            let next_hex = last_hex + vdir[rng.gen_range(0, vdir.len())];
             last_hex = next_hex;
-            hexmap.insert(last_hex, true);
+            tiles.push(last_hex);
         }
         let goal = last_hex;
 
-        hexmap.insert(start, true);
-        hexmap.insert(goal, true);
-        for i in 0..6 {
-            hexmap.insert(start + DIR[i], true);
-            hexmap.insert(goal + DIR[i], true);
+        tiles.push(start);
+        tiles.push(goal);
+        for dir in DIR {
+            tiles.push(start + dir);
+            tiles.push(goal + dir);
         }
 
+        // A 20 step walk covers a few hundred cells at most
+        let hexmap = HexGrid::try_from_iter(tiles).expect("generated maps are small");
+
         Self {
             hexmap,
             start,
@@ -235,34 +200,154 @@ impl HexMap {
     ];
 }
 
-pub fn load_map(arr: [[char; 9]; 9]) -> HexMap {
-    let mut hexmap = HashMap::new();
-    let mut start = Hex::from_axial(0, 0);
-    let mut goal = Hex::from_axial(0, 0);
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    MissingStart,
+    MissingGoal,
+    DuplicateStart(Vec<Hex>),
+    DuplicateGoal(Vec<Hex>),
+    UnreachableGoal { start: Hex, goal: Hex },
+    UnknownSymbol { pos: Hex, symbol: char },
+}
+
+fn fmt_hexes(hexes: &[Hex]) -> String {
+    hexes
+        .iter()
+        .map(|hex| format!("({}, {})", hex.q, hex.r))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::MissingStart => write!(f, "map has no start tile"),
+            MapError::MissingGoal => write!(f, "map has no goal tile"),
+            MapError::DuplicateStart(hexes) => {
+                write!(f, "map has several start tiles at {}", fmt_hexes(hexes))
+            }
+            MapError::DuplicateGoal(hexes) => {
+                write!(f, "map has several goal tiles at {}", fmt_hexes(hexes))
+            }
+            MapError::UnreachableGoal { start, goal } => write!(
+                f,
+                "goal at {} can't be reached from start at {}",
+                fmt_hexes(&[*goal]),
+                fmt_hexes(&[*start])
+            ),
+            MapError::UnknownSymbol { pos, symbol } => {
+                write!(f, "unknown symbol `{}` at {}", symbol, fmt_hexes(&[*pos]))
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl HexMap {
+    // Start and goal must be tiles and the goal must be reachable standing up.
+    pub fn validate(&self) -> Result<(), MapError> {
+        if !self.is_in_map(self.start) {
+            return Err(MapError::MissingStart);
+        }
+
+        if !self.is_in_map(self.goal) {
+            return Err(MapError::MissingGoal);
+        }
+
+        if self.solve_path(self.start).is_none() {
+            return Err(MapError::UnreachableGoal {
+                start: self.start,
+                goal: self.goal,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+pub fn load_map(arr: [[char; 9]; 9]) -> Result<HexMap, MapError> {
+    let mut tiles = Vec::new();
+    let mut starts = Vec::new();
+    let mut goals = Vec::new();
 
-    for r in 0..9 {
-        for q in 0..9 {
+    for (r, row) in arr.iter().enumerate() {
+        for (q, tile) in row.iter().enumerate() {
             let hex = Hex::from_axial(q as i32, r as i32);
-            match arr[r][q] {
+            match tile {
+                '.' => {}
                 '*' => {
-                    hexmap.insert(hex, true);
+                    tiles.push(hex);
                 }
                 'A' => {
-                    hexmap.insert(hex, true);
-                    start = hex;
+                    tiles.push(hex);
+                    starts.push(hex);
                 }
                 'X' => {
-                    hexmap.insert(hex, true);
-                    goal = hex;
+                    tiles.push(hex);
+                    goals.push(hex);
+                }
+                _ => {
+                    return Err(MapError::UnknownSymbol {
+                        pos: hex,
+                        symbol: *tile,
+                    });
                 }
-                _ => {}
             }
         }
     }
 
-    HexMap {
+    let hexmap = HexGrid::try_from_iter(tiles).expect("a 9x9 map fits in the grid");
+
+    let start = match starts[..] {
+        [] => return Err(MapError::MissingStart),
+        [start] => start,
+        _ => return Err(MapError::DuplicateStart(starts)),
+    };
+
+    let goal = match goals[..] {
+        [] => return Err(MapError::MissingGoal),
+        [goal] => goal,
+        _ => return Err(MapError::DuplicateGoal(goals)),
+    };
+
+    let map = HexMap {
         hexmap,
         start,
         goal,
+    };
+    map.validate()?;
+
+    Ok(map)
+}
+
+// The four built-in maps, for tests
+#[cfg(test)]
+pub fn premade_maps() -> Vec<HexMap> {
+    [
+        HexMap::PREMADE_MAP_0,
+        HexMap::PREMADE_MAP_1,
+        HexMap::PREMADE_MAP_2,
+        HexMap::PREMADE_MAP_3,
+    ]
+    .into_iter()
+    .map(|arr| load_map(arr).unwrap())
+    .collect()
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::core::game::PlayerState;
+
+    #[test]
+    fn json_roundtrip() {
+        let map = load_map(HexMap::PREMADE_MAP_2).unwrap();
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(serde_json::from_str::<HexMap>(&json).unwrap(), map);
+
+        let state = PlayerState::Flat(map.start, map.goal);
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<PlayerState>(&json).unwrap(), state);
     }
 }
@@ -1,3 +1,18 @@
+pub mod analysis;
+pub mod canonical;
+pub mod code;
+pub mod editor;
+pub mod event;
 pub mod game;
+pub mod grid;
 pub mod hex;
+pub mod input;
+pub mod level;
 pub mod map;
+pub mod notation;
+pub mod pack;
+pub mod pause;
+pub mod render;
+pub mod replay;
+pub mod rng;
+pub mod solver;
@@ -0,0 +1,232 @@
+use std::fmt;
+
+use crate::core::hex::*;
+
+// Move notation, for posting solutions and replays as text.
+//
+//     E NE NW W SW SE       compass directions, upper case
+//     d e w a z x           the movement keys, lower case, as they point on
+//                           a board that isn't rotated
+//
+// A number in front of a move repeats it, so `3E` is `E E E`. Whitespace
+// and commas between moves are optional: `2E SE, NW` and `eex` both parse.
+// Formatting always writes compass directions separated by spaces.
+
+// Keeps a typo like `99999999E` from allocating forever
+pub const MAX_MOVES: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotationErrorKind {
+    UnknownMove(char),
+    MissingMove,
+    InvalidCount,
+    TooLong,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotationError {
+    // Character position, counting from 1
+    pub column: usize,
+    pub kind: NotationErrorKind,
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.column)?;
+
+        match &self.kind {
+            NotationErrorKind::UnknownMove(c) => write!(f, "unknown move '{}'", c),
+            NotationErrorKind::MissingMove => write!(f, "expected a move after the count"),
+            NotationErrorKind::InvalidCount => write!(f, "invalid move count"),
+            NotationErrorKind::TooLong => write!(f, "more than {} moves", MAX_MOVES),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+impl HexDirection {
+    pub fn compass(self) -> &'static str {
+        match self {
+            HexDirection::E => "E",
+            HexDirection::NE => "NE",
+            HexDirection::NW => "NW",
+            HexDirection::W => "W",
+            HexDirection::SW => "SW",
+            HexDirection::SE => "SE",
+        }
+    }
+
+    pub fn key(self) -> char {
+        match self {
+            HexDirection::E => 'd',
+            HexDirection::NE => 'e',
+            HexDirection::NW => 'w',
+            HexDirection::W => 'a',
+            HexDirection::SW => 'z',
+            HexDirection::SE => 'x',
+        }
+    }
+}
+
+pub fn format_moves(moves: &[HexDirection]) -> String {
+    let mut tokens = Vec::new();
+
+    for run in moves.chunk_by(|a, b| a == b) {
+        let name = run[0].compass();
+        if run.len() == 1 {
+            tokens.push(name.to_owned());
+        } else {
+            tokens.push(format!("{}{}", run.len(), name));
+        }
+    }
+
+    tokens.join(" ")
+}
+
+pub fn parse_moves(text: &str) -> Result<Vec<HexDirection>, NotationError> {
+    let mut moves = Vec::new();
+    let mut chars = text.chars().enumerate().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let column = i + 1;
+        let error = |kind| NotationError { column, kind };
+
+        if c.is_whitespace() || c == ',' {
+            continue;
+        }
+
+        let mut count = 1;
+        let mut c = c;
+        if c.is_ascii_digit() {
+            let mut digits = String::from(c);
+            while let Some(&(_, d)) = chars.peek().filter(|(_, d)| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+
+            count = digits
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or(error(NotationErrorKind::InvalidCount))?;
+            c = chars
+                .next()
+                .map(|(_, c)| c)
+                .ok_or(error(NotationErrorKind::MissingMove))?;
+        }
+
+        let dir = match c {
+            'E' => HexDirection::E,
+            'W' => HexDirection::W,
+            'N' | 'S' => {
+                let second = chars.next_if(|(_, d)| *d == 'E' || *d == 'W');
+                match (c, second.map(|(_, d)| d)) {
+                    ('N', Some('E')) => HexDirection::NE,
+                    ('N', Some('W')) => HexDirection::NW,
+                    ('S', Some('E')) => HexDirection::SE,
+                    ('S', Some('W')) => HexDirection::SW,
+                    _ => return Err(error(NotationErrorKind::UnknownMove(c))),
+                }
+            }
+            'd' => HexDirection::E,
+            'e' => HexDirection::NE,
+            'w' => HexDirection::NW,
+            'a' => HexDirection::W,
+            'z' => HexDirection::SW,
+            'x' => HexDirection::SE,
+            _ => return Err(error(NotationErrorKind::UnknownMove(c))),
+        };
+
+        // `moves` never holds more than `MAX_MOVES`, so this can't underflow
+        // the way adding a huge count could overflow
+        if count > MAX_MOVES - moves.len() {
+            return Err(error(NotationErrorKind::TooLong));
+        }
+        moves.extend(std::iter::repeat_n(dir, count));
+    }
+
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> (usize, NotationErrorKind) {
+        let e = parse_moves(text).unwrap_err();
+        (e.column, e.kind)
+    }
+
+    #[test]
+    fn format_then_parse_roundtrips() {
+        let moves = [
+            vec![],
+            vec![HexDirection::E],
+            HEX_DIRECTIONS.to_vec(),
+            vec![
+                HexDirection::SE,
+                HexDirection::SE,
+                HexDirection::NW,
+                HexDirection::E,
+                HexDirection::E,
+                HexDirection::E,
+            ],
+        ];
+
+        for moves in moves {
+            assert_eq!(parse_moves(&format_moves(&moves)).unwrap(), moves);
+        }
+    }
+
+    #[test]
+    fn formats_runs_with_counts() {
+        use HexDirection::*;
+        assert_eq!(format_moves(&[E, E, SE, NW, NW, NW]), "2E SE 3NW");
+        assert_eq!(format_moves(&[]), "");
+    }
+
+    #[test]
+    fn keys_compass_and_separators_parse_the_same() {
+        let compass = parse_moves("E SE SW W NW NE").unwrap();
+        assert_eq!(compass, HEX_DIRECTIONS.to_vec());
+        assert_eq!(parse_moves("dxzawe").unwrap(), compass);
+        assert_eq!(parse_moves("E,SE, SW\tW NWNE").unwrap(), compass);
+
+        let keys = HEX_DIRECTIONS
+            .map(|dir| dir.key())
+            .iter()
+            .collect::<String>();
+        assert_eq!(parse_moves(&keys).unwrap(), compass);
+        assert_eq!(parse_moves("2E x").unwrap(), parse_moves("ddx").unwrap());
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error("E Q"), (3, NotationErrorKind::UnknownMove('Q')));
+        assert_eq!(error("E N"), (3, NotationErrorKind::UnknownMove('N')));
+        assert_eq!(error("SE SX"), (4, NotationErrorKind::UnknownMove('S')));
+        assert_eq!(error("E 3"), (3, NotationErrorKind::MissingMove));
+        assert_eq!(error("E  0E"), (4, NotationErrorKind::InvalidCount));
+        assert_eq!(
+            error("E 99999999999999999999999E"),
+            (3, NotationErrorKind::InvalidCount)
+        );
+    }
+
+    #[test]
+    fn huge_counts_are_too_long_not_an_overflow() {
+        assert_eq!(
+            error("E 18446744073709551615E"),
+            (3, NotationErrorKind::TooLong)
+        );
+        assert_eq!(
+            error(&format!("{}E NE", MAX_MOVES)),
+            (9, NotationErrorKind::TooLong)
+        );
+        assert_eq!(
+            parse_moves(&format!("{}E", MAX_MOVES)).unwrap().len(),
+            MAX_MOVES
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use macroquad::file::load_string;
+
+use crate::core::analysis::Difficulty;
+use crate::core::level::*;
+use crate::core::map::*;
+
+// Level packs live under `assets/levels`. `levels/index` lists one pack
+// directory per line, and every pack directory has a `manifest`:
+//
+//     name: Classic
+//     author: sifmuna00
+//     ---
+//     01.hexl
+//     02.hexl  # par 12, medium
+//
+// The files are listed explicitly because wasm builds can't read directories.
+// Anything after `#` on a file line is a note for people reading it.
+
+pub const LEVELS_DIR: &str = "levels";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub author: String,
+    pub files: Vec<String>,
+}
+
+impl FromStr for Manifest {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut manifest = Manifest {
+            name: String::new(),
+            author: String::new(),
+            files: Vec::new(),
+        };
+        let mut in_header = true;
+        let mut last_line = 0;
+
+        for (i, line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            last_line = line_no;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if !in_header {
+                let file = trimmed.split('#').next().unwrap_or_default().trim();
+                manifest.files.push(file.to_owned());
+                continue;
+            }
+
+            if trimmed == HEADER_END {
+                in_header = false;
+                continue;
+            }
+
+            let column = line.len() - line.trim_start().len() + 1;
+            let Some((key, value)) = trimmed.split_once(':') else {
+                return Err(ParseError {
+                    line: line_no,
+                    column,
+                    kind: ParseErrorKind::MissingValue,
+                });
+            };
+
+            match key.trim() {
+                "name" => manifest.name = value.trim().to_owned(),
+                "author" => manifest.author = value.trim().to_owned(),
+                key => {
+                    return Err(ParseError {
+                        line: line_no,
+                        column,
+                        kind: ParseErrorKind::UnknownKey(key.to_owned()),
+                    })
+                }
+            }
+        }
+
+        if in_header {
+            return Err(ParseError {
+                line: last_line + 1,
+                column: 1,
+                kind: ParseErrorKind::MissingSeparator,
+            });
+        }
+
+        Ok(manifest)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelPack {
+    pub name: String,
+    pub author: String,
+    // Levels that failed to load keep their slot so the level select can
+    // show what went wrong.
+    pub levels: Vec<Result<Level, String>>,
+    // Worked out once on load, by level index
+    pub difficulty: Vec<Option<Difficulty>>,
+}
+
+impl LevelPack {
+    pub fn new(name: String, author: String, levels: Vec<Result<Level, String>>) -> Self {
+        let difficulty = levels
+            .iter()
+            .map(|level| level.as_ref().ok()?.map.difficulty())
+            .collect();
+
+        LevelPack {
+            name,
+            author,
+            levels,
+            difficulty,
+        }
+    }
+
+    pub async fn load(dir: &str) -> Result<Self, String> {
+        let path = format!("{}/{}/manifest", LEVELS_DIR, dir);
+        let src = load_string(&path)
+            .await
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let manifest = src
+            .parse::<Manifest>()
+            .map_err(|e| format!("{}:{}", path, e))?;
+
+        let mut levels = Vec::new();
+        for file in &manifest.files {
+            let path = format!("{}/{}/{}", LEVELS_DIR, dir, file);
+            levels.push(Self::load_level(&path).await);
+        }
+
+        let name = if manifest.name.is_empty() {
+            dir.to_owned()
+        } else {
+            manifest.name
+        };
+
+        Ok(LevelPack::new(name, manifest.author, levels))
+    }
+
+    async fn load_level(path: &str) -> Result<Level, String> {
+        let src = load_string(path)
+            .await
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let level = src
+            .parse::<Level>()
+            .map_err(|e| format!("{}:{}", path, e))?;
+        level
+            .map
+            .validate()
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        Ok(level)
+    }
+
+    // Every pack listed in `levels/index`, in order. Packs that fail to load
+    // are skipped and reported.
+    pub async fn load_all() -> (Vec<Self>, Vec<String>) {
+        let mut packs = Vec::new();
+        let mut errors = Vec::new();
+
+        let path = format!("{}/index", LEVELS_DIR);
+        let index = match load_string(&path).await {
+            Ok(index) => index,
+            Err(e) => {
+                errors.push(format!("{}: {}", path, e));
+                return (packs, errors);
+            }
+        };
+
+        for dir in index.lines().map(str::trim) {
+            if dir.is_empty() || dir.starts_with('#') {
+                continue;
+            }
+
+            match LevelPack::load(dir).await {
+                Ok(pack) => {
+                    for (copy, original) in pack.duplicates() {
+                        errors.push(format!(
+                            "{}/{}: level {} is the same puzzle as level {}",
+                            LEVELS_DIR,
+                            dir,
+                            copy + 1,
+                            original + 1
+                        ));
+                    }
+                    packs.push(pack);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (packs, errors)
+    }
+
+    // Pairs of (copy, original) level indices that are the same puzzle up to
+    // rotation, reflection and translation
+    pub fn duplicates(&self) -> Vec<(usize, usize)> {
+        let mut seen = HashMap::new();
+        let mut duplicates = Vec::new();
+
+        for (i, level) in self.levels.iter().enumerate() {
+            // Maps too spread out to turn aren't compared
+            let Some(code) = level
+                .as_ref()
+                .ok()
+                .and_then(|l| l.map.canonical_code().ok())
+            else {
+                continue;
+            };
+
+            match seen.get(&code) {
+                Some(original) => duplicates.push((i, *original)),
+                None => {
+                    seen.insert(code, i);
+                }
+            }
+        }
+
+        duplicates
+    }
+
+    // The original hard-coded levels, used when no pack could be loaded.
+    pub fn builtin() -> Self {
+        let levels = [
+            HexMap::PREMADE_MAP_0,
+            HexMap::PREMADE_MAP_1,
+            HexMap::PREMADE_MAP_2,
+            HexMap::PREMADE_MAP_3,
+        ]
+        .into_iter()
+        .map(|arr| {
+            load_map(arr)
+                .map(Level::from_map)
+                .map_err(|e| e.to_string())
+        })
+        .collect();
+
+        LevelPack::new(String::from("Classic"), String::new(), levels)
+    }
+}
@@ -0,0 +1,221 @@
+use macroquad::prelude::*;
+
+use crate::core::game::*;
+use crate::core::input::MenuControl;
+
+// What the pause menu hands back to `main.rs` to act on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseItem {
+    Resume,
+    Restart,
+    Undo,
+    LevelSelect,
+    Settings,
+    Quit,
+}
+
+const PAUSE_ITEMS: [PauseItem; 6] = [
+    PauseItem::Resume,
+    PauseItem::Restart,
+    PauseItem::Undo,
+    PauseItem::LevelSelect,
+    PauseItem::Settings,
+    PauseItem::Quit,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SettingsItem {
+    FlatLayout,
+    Lives,
+    Back,
+}
+
+const SETTINGS_ITEMS: [SettingsItem; 3] = [
+    SettingsItem::FlatLayout,
+    SettingsItem::Lives,
+    SettingsItem::Back,
+];
+
+const ITEM_SIZE: f32 = 40.0;
+const ITEM_SPACING: f32 = 50.0;
+
+// Up/Down or hovering picks an item, Enter, Space or a click takes it, and
+// Esc backs out. A gamepad's D-pad, A and B or Start do the same through
+// `MenuControl`.
+#[derive(Debug, Default)]
+pub struct PauseMenu {
+    pub selected: usize,
+    pub in_settings: bool,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        PauseMenu::default()
+    }
+
+    fn labels(&self, game: &Game) -> Vec<String> {
+        if !self.in_settings {
+            return PAUSE_ITEMS
+                .iter()
+                .map(|item| match item {
+                    PauseItem::Resume => "Resume",
+                    PauseItem::Restart => "Restart",
+                    PauseItem::Undo => "Undo",
+                    PauseItem::LevelSelect => "Level Select",
+                    PauseItem::Settings => "Settings",
+                    PauseItem::Quit => "Quit to menu",
+                })
+                .map(str::to_owned)
+                .collect();
+        }
+
+        SETTINGS_ITEMS
+            .iter()
+            .map(|item| match item {
+                SettingsItem::FlatLayout => {
+                    let state = if game.is_flat() { "on" } else { "off" };
+                    format!("< Flat layout: {} >", state)
+                }
+                SettingsItem::Lives => match game.max_lives {
+                    Some(lives) => format!("< Lives: {} >", lives),
+                    None => "< Lives: unlimited >".to_owned(),
+                },
+                SettingsItem::Back => "Back".to_owned(),
+            })
+            .collect()
+    }
+
+    // Where each item sits on screen, for the mouse
+    fn item_rects(&self, game: &Game) -> Vec<Rect> {
+        let labels = self.labels(game);
+        let top = screen_height() / 2.0 - ITEM_SPACING * labels.len() as f32 / 2.0;
+
+        labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let size = measure_text(label, None, ITEM_SIZE as u16, 1.0);
+                Rect::new(
+                    screen_width() / 2.0 - size.width / 2.0,
+                    top + ITEM_SPACING * i as f32,
+                    size.width,
+                    ITEM_SPACING,
+                )
+            })
+            .collect()
+    }
+
+    // Settings are changed on the spot, anything else is handed back
+    pub fn update(&mut self, game: &mut Game, controls: &[MenuControl]) -> Option<PauseItem> {
+        let is_pressed = |control| controls.contains(&control);
+
+        let count = if self.in_settings {
+            SETTINGS_ITEMS.len()
+        } else {
+            PAUSE_ITEMS.len()
+        };
+
+        if is_pressed(MenuControl::Up) {
+            self.selected = (self.selected + count - 1) % count;
+        }
+        if is_pressed(MenuControl::Down) {
+            self.selected = (self.selected + 1) % count;
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        let hovered = self
+            .item_rects(game)
+            .iter()
+            .position(|rect| rect.contains(mouse));
+        if let Some(i) = hovered {
+            if mouse_delta_position() != Vec2::ZERO {
+                self.selected = i;
+            }
+        }
+
+        let is_clicked = is_mouse_button_pressed(MouseButton::Left) && hovered.is_some();
+        if let Some(i) = hovered.filter(|_| is_clicked) {
+            self.selected = i;
+        }
+        let is_taken = is_pressed(MenuControl::Select) || is_clicked;
+
+        if self.in_settings {
+            // Values step with Left/Right too, Left going back
+            let step = if is_pressed(MenuControl::Left) {
+                -1
+            } else if is_taken || is_pressed(MenuControl::Right) {
+                1
+            } else {
+                0
+            };
+
+            match SETTINGS_ITEMS[self.selected] {
+                SettingsItem::FlatLayout if step != 0 => game.set_flat(!game.is_flat()),
+                SettingsItem::Lives if step != 0 => game.cycle_lives(step),
+                SettingsItem::Back if is_taken => self.close_settings(),
+                _ => {}
+            }
+            if is_pressed(MenuControl::Back) {
+                self.close_settings();
+            }
+            return None;
+        }
+
+        if is_pressed(MenuControl::Back) {
+            return Some(PauseItem::Resume);
+        }
+        if !is_taken {
+            return None;
+        }
+
+        match PAUSE_ITEMS[self.selected] {
+            PauseItem::Settings => {
+                self.in_settings = true;
+                self.selected = 0;
+                None
+            }
+            item => Some(item),
+        }
+    }
+
+    fn close_settings(&mut self) {
+        self.in_settings = false;
+        self.selected = PAUSE_ITEMS
+            .iter()
+            .position(|item| *item == PauseItem::Settings)
+            .unwrap_or(0);
+    }
+
+    // Draws on top of `Game::draw`
+    pub fn draw(&self, game: &Game) {
+        set_default_camera();
+        draw_rectangle(
+            0.0,
+            0.0,
+            screen_width(),
+            screen_height(),
+            Color::new(0.0, 0.0, 0.0, 0.6),
+        );
+
+        let title = if self.in_settings {
+            "SETTINGS"
+        } else {
+            "PAUSED"
+        };
+        let rects = self.item_rects(game);
+        let size = measure_text(title, None, 50, 1.0);
+        let top = rects.first().map_or(screen_height() / 2.0, |rect| rect.y);
+        draw_text(
+            title,
+            screen_width() / 2.0 - size.width / 2.0,
+            top - 30.0,
+            50.0,
+            RED,
+        );
+
+        for (i, (label, rect)) in self.labels(game).iter().zip(&rects).enumerate() {
+            let color = if i == self.selected { YELLOW } else { GRAY };
+            draw_text(label, rect.x, rect.y + ITEM_SIZE, ITEM_SIZE, color);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use macroquad::prelude::*;
+
+use crate::core::game::PlayerState;
+use crate::core::hex::*;
+use crate::core::level::*;
+use crate::core::map::*;
+
+// Pictures of a map fitted to its tiles, for docs, reviewing level packs and
+// thumbnails. Neither needs a window.
+
+// Tiles the solution goes over in ASCII pictures
+pub const TILE_PATH: char = 'o';
+
+// Circumradius of a hex in SVG pictures, in pixels
+const SVG_HEX_SIZE: f32 = 20.0;
+const SVG_MARGIN: f32 = 10.0;
+
+impl HexMap {
+    // The states the block goes through on `moves`, the start included
+    fn path_states(&self, moves: &[HexDirection]) -> Vec<PlayerState> {
+        let mut state = PlayerState::Standing(self.start);
+        let mut states = vec![state];
+
+        for &dir in moves {
+            state = state.next_state_in_map(dir, self);
+            states.push(state);
+        }
+
+        states
+    }
+
+    // The same grid as in .hexl files, with the tiles the solution goes over
+    // marked as `TILE_PATH`
+    pub fn to_ascii(&self, solution: Option<&[HexDirection]>) -> String {
+        let path = self
+            .path_states(solution.unwrap_or_default())
+            .iter()
+            .flat_map(PlayerState::hexes)
+            .collect::<Vec<_>>();
+
+        let offsets = self
+            .hexmap
+            .iter()
+            .map(|hex| hex.to_offset())
+            .collect::<Vec<_>>();
+
+        let min_col = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+        let max_col = offsets.iter().map(|o| o.0).max().unwrap_or(0);
+        let min_row = offsets.iter().map(|o| o.1).min().unwrap_or(0);
+        let max_row = offsets.iter().map(|o| o.1).max().unwrap_or(0);
+
+        let mut text = String::new();
+        for row in min_row..=max_row {
+            // Even rows are shoved right half a hex
+            if row & 1 == 0 {
+                text.push(' ');
+            }
+
+            for col in min_col..=max_col {
+                let hex = Hex::from_offset((col, row));
+                let tile = if !self.is_in_map(hex) {
+                    TILE_EMPTY
+                } else if hex == self.start {
+                    TILE_START
+                } else if hex == self.goal {
+                    TILE_GOAL
+                } else if path.contains(&hex) {
+                    TILE_PATH
+                } else {
+                    TILE_FLOOR
+                };
+
+                text.push(tile);
+                text.push(' ');
+            }
+
+            text.truncate(text.trim_end().len());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    // Hex polygons with the start and goal circled, and the solution drawn
+    // through the middle of the block at each step
+    pub fn to_svg(&self, solution: Option<&[HexDirection]>) -> String {
+        let layout = Layout {
+            orientation: Orientation::LAYOUT_POINTY,
+            size: vec2(SVG_HEX_SIZE, SVG_HEX_SIZE),
+            origin: Vec2::ZERO,
+        };
+
+        let corners = self
+            .hexmap
+            .iter()
+            .flat_map(|hex| layout.polygon_corners(hex))
+            .collect::<Vec<_>>();
+        let min = corners
+            .iter()
+            .copied()
+            .reduce(Vec2::min)
+            .unwrap_or_default()
+            - SVG_MARGIN;
+        let max = corners
+            .iter()
+            .copied()
+            .reduce(Vec2::max)
+            .unwrap_or_default()
+            + SVG_MARGIN;
+        let size = max - min;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{:.1} {:.1} {:.1} {:.1}" width="{:.0}" height="{:.0}">"#,
+            min.x, min.y, size.x, size.y, size.x, size.y
+        );
+
+        for hex in self.hexmap.iter() {
+            let points = layout
+                .polygon_corners(hex)
+                .iter()
+                .map(|p| format!("{:.1},{:.1}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                r##"  <polygon points="{}" fill="#5a8c5a" stroke="#1e3c1e"/>"##,
+                points
+            );
+        }
+
+        let start = layout.hex_to_pixel(self.start);
+        let _ = writeln!(
+            svg,
+            r##"  <circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="#f0c040"/>"##,
+            start.x,
+            start.y,
+            SVG_HEX_SIZE * 0.4
+        );
+        let goal = layout.hex_to_pixel(self.goal);
+        let _ = writeln!(
+            svg,
+            r##"  <circle cx="{:.1}" cy="{:.1}" r="{:.1}" fill="none" stroke="#e04040" stroke-width="3"/>"##,
+            goal.x,
+            goal.y,
+            SVG_HEX_SIZE * 0.5
+        );
+
+        if let Some(moves) = solution {
+            let points = self
+                .path_states(moves)
+                .iter()
+                .filter(|state| !state.is_dead())
+                .map(|state| {
+                    let hexes = state.hexes();
+                    let sum = hexes
+                        .iter()
+                        .fold(Vec2::ZERO, |sum, hex| sum + layout.hex_to_pixel(*hex));
+                    let p = sum / hexes.len() as f32;
+                    format!("{:.1},{:.1}", p.x, p.y)
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let _ = writeln!(
+                svg,
+                r##"  <polyline points="{}" fill="none" stroke="#4060ff" stroke-width="3" stroke-linejoin="round"/>"##,
+                points
+            );
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
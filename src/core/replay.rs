@@ -0,0 +1,554 @@
+use std::fmt;
+use std::str::FromStr;
+
+use macroquad::prelude::*;
+
+use crate::core::code::CodeError;
+use crate::core::game::*;
+use crate::core::hex::*;
+use crate::core::input::*;
+use crate::core::level::*;
+use crate::core::map::*;
+use crate::core::notation::*;
+
+// .hexr replay files
+//
+//     code: AQUDAAjfMw
+//     seed: 0
+//     ---
+//     0.52 E
+//     1.10 SE
+//
+// `code` is the level code of the map played, so a replay doesn't depend on
+// which packs are installed, and `seed` is what the generator made it from
+// (0 for hand-made levels). Every line after `---` is one move: seconds
+// since the attempt started, then the move in move notation.
+
+// Every attempt gets its own file in here
+#[cfg(not(target_arch = "wasm32"))]
+pub const REPLAY_DIR: &str = "replays";
+
+// Older replays are deleted past this many
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_SAVED: usize = 100;
+
+// Playback speeds, as multiples of the recorded speed
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub code: String,
+    pub seed: u64,
+    pub inputs: Vec<(f64, HexDirection)>,
+}
+
+impl Replay {
+    // Only maps too spread out for a code have none, and they can't be
+    // played anyway
+    pub fn new(map: &HexMap, seed: u64) -> Self {
+        Replay {
+            code: map.to_code().unwrap_or_default(),
+            seed,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn map(&self) -> Result<HexMap, CodeError> {
+        HexMap::from_code(&self.code)
+    }
+
+    // Saves a new file in `REPLAY_DIR` named after when it was saved and
+    // `label`, e.g. `1760000000000-pack-1-level-3.hexr`, and returns its path.
+    // There is no file system on wasm, so nothing is kept there.
+    pub fn save(&self, label: &str) -> Result<String, String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            std::fs::create_dir_all(REPLAY_DIR)
+                .map_err(|e| format!("Failed to save {}: {}", REPLAY_DIR, e))?;
+
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis());
+            let mut path = format!("{}/{:013}-{}.hexr", REPLAY_DIR, millis, label);
+            let mut n = 1;
+            while std::path::Path::new(&path).exists() {
+                n += 1;
+                path = format!("{}/{:013}-{}-{}.hexr", REPLAY_DIR, millis, label, n);
+            }
+
+            std::fs::write(&path, self.to_string())
+                .map_err(|e| format!("Failed to save {}: {}", path, e))?;
+
+            for old in Replay::saved().iter().skip(MAX_SAVED) {
+                let _ = std::fs::remove_file(old);
+            }
+
+            Ok(path)
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = label;
+            Ok(String::new())
+        }
+    }
+
+    // Paths of the saved replays, newest first. On wasm the only one is
+    // whatever was last pasted into the page.
+    pub fn saved() -> Vec<String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let Ok(entries) = std::fs::read_dir(REPLAY_DIR) else {
+                return Vec::new();
+            };
+
+            // The names start with the time, so they sort oldest first
+            let mut paths = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "hexr"))
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            paths.sort_unstable_by(|a, b| b.cmp(a));
+            paths
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            vec!["clipboard".to_owned()]
+        }
+    }
+
+    // Reads a path from `Replay::saved`
+    pub fn load(path: &str) -> Result<Self, String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        #[cfg(target_arch = "wasm32")]
+        let src = miniquad::window::clipboard_get().unwrap_or_default();
+
+        src.parse().map_err(|e| format!("{}:{}", path, e))
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let mut replay = Replay {
+            code: String::new(),
+            seed: 0,
+            inputs: Vec::new(),
+        };
+        let mut in_header = true;
+        let mut last_line = 0;
+
+        for (i, line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            last_line = line_no;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let column = line.len() - line.trim_start().len() + 1;
+            let error = |kind| ParseError {
+                line: line_no,
+                column,
+                kind,
+            };
+
+            if !in_header {
+                let (time, moves) = trimmed
+                    .split_once(char::is_whitespace)
+                    .ok_or(error(ParseErrorKind::MissingValue))?;
+                let time = time
+                    .parse::<f64>()
+                    .map_err(|_| error(ParseErrorKind::InvalidNumber(time.to_owned())))?;
+                let moves = parse_moves(moves)
+                    .map_err(|e| error(ParseErrorKind::InvalidMove(e.to_string())))?;
+
+                replay
+                    .inputs
+                    .extend(moves.into_iter().map(|dir| (time, dir)));
+                continue;
+            }
+
+            if trimmed == HEADER_END {
+                in_header = false;
+                continue;
+            }
+
+            let (key, value) = trimmed
+                .split_once(':')
+                .ok_or(error(ParseErrorKind::MissingValue))?;
+            let value = value.trim();
+
+            match key.trim() {
+                "code" => replay.code = value.to_owned(),
+                "seed" => {
+                    replay.seed = value
+                        .parse()
+                        .map_err(|_| error(ParseErrorKind::InvalidNumber(value.to_owned())))?
+                }
+                key => return Err(error(ParseErrorKind::UnknownKey(key.to_owned()))),
+            }
+        }
+
+        if in_header {
+            return Err(ParseError {
+                line: last_line + 1,
+                column: 1,
+                kind: ParseErrorKind::MissingSeparator,
+            });
+        }
+
+        Ok(replay)
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "code: {}", self.code)?;
+        writeln!(f, "seed: {}", self.seed)?;
+        writeln!(f, "{}", HEADER_END)?;
+
+        for (time, dir) in &self.inputs {
+            writeln!(f, "{:.3} {}", time, dir.compass())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BadMove {
+    // The block fell off the map
+    Fell,
+    // The attempt was already won or lost
+    AfterEnd,
+    // Recorded earlier than the move before it
+    OutOfOrder,
+}
+
+impl fmt::Display for BadMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BadMove::Fell => write!(f, "falls off the map"),
+            BadMove::AfterEnd => write!(f, "comes after the attempt ended"),
+            BadMove::OutOfOrder => write!(f, "is timed before the move ahead of it"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub is_won: bool,
+    // Moves made before the attempt ended or went wrong
+    pub moves: usize,
+    // The first move that lost or broke the rules, counting from 1
+    pub first_bad: Option<(usize, HexDirection, BadMove)>,
+}
+
+impl Replay {
+    // Plays the replay against its level without a window, through the same
+    // moves as live play
+    pub fn verify(&self) -> Result<Verification, CodeError> {
+        let map = self.map()?;
+        let goal = PlayerState::Standing(map.goal);
+        let mut state = PlayerState::Standing(map.start);
+        let mut last_time = 0.0;
+
+        let mut verification = Verification {
+            is_won: false,
+            moves: 0,
+            first_bad: None,
+        };
+
+        for (i, &(time, dir)) in self.inputs.iter().enumerate() {
+            let bad = if state == goal || state.is_dead() {
+                Some(BadMove::AfterEnd)
+            } else if time < last_time {
+                Some(BadMove::OutOfOrder)
+            } else {
+                state = state.next_state_in_map(dir, &map);
+                verification.moves += 1;
+                state.is_dead().then_some(BadMove::Fell)
+            };
+
+            if let Some(bad) = bad {
+                verification.first_bad = Some((i + 1, dir, bad));
+                break;
+            }
+            last_time = time;
+        }
+
+        verification.is_won = verification.first_bad.is_none() && state == goal;
+        Ok(verification)
+    }
+}
+
+// Plays a replay back on the board, through the same moves as live play
+pub struct Playback {
+    pub replay: Replay,
+    // Index of the next input to play
+    pub next: usize,
+    // Seconds into the replay
+    pub time: f64,
+    pub speed: f64,
+    pub is_paused: bool,
+    // Moves asked for one at a time while paused
+    steps: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            next: 0,
+            time: 0.0,
+            speed: 1.0,
+            is_paused: false,
+            steps: 0,
+        }
+    }
+
+    // Puts the replay's map on the board and rewinds to the start
+    pub fn start(&mut self, game: &mut Game) -> Result<(), CodeError> {
+        game.play_map(self.replay.map()?);
+        game.stop_recording();
+        game.seed = self.replay.seed;
+
+        self.next = 0;
+        self.time = 0.0;
+        self.steps = 0;
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.replay.inputs.len()
+    }
+
+    // Draws on top of `Game::draw`
+    pub fn draw(&self) {
+        set_default_camera();
+
+        let state = if self.is_finished() {
+            "finished"
+        } else if self.is_paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        let text = format!(
+            "Replay {}/{}  x{}  {}",
+            self.next,
+            self.replay.inputs.len(),
+            self.speed,
+            state
+        );
+        draw_text(&text, 10.0, 40.0, 40.0, GREEN);
+
+        draw_text(
+            "SPACE pause, N step, UP/DOWN speed, BACKSPACE restart, ESC exit",
+            10.0,
+            screen_height() - 20.0,
+            24.0,
+            GRAY,
+        );
+    }
+}
+
+impl Playback {
+    // Controls for this frame, e.g. from the keyboard
+    pub fn update(&mut self, input: &mut impl PlaybackInput, game: &mut Game) {
+        for control in input.controls() {
+            self.apply_control(control, game);
+        }
+    }
+
+    pub fn apply_control(&mut self, control: PlaybackControl, game: &mut Game) {
+        match control {
+            PlaybackControl::TogglePause => self.is_paused = !self.is_paused,
+            PlaybackControl::Faster => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            PlaybackControl::Slower => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+            PlaybackControl::Step => {
+                if self.is_paused {
+                    self.steps += 1;
+                }
+            }
+            PlaybackControl::Rewind => {
+                self.next = 0;
+                self.time = 0.0;
+                self.steps = 0;
+                game.restart();
+            }
+        }
+    }
+}
+
+// Feeds the recorded moves to `Game::update` as they come due
+impl InputSource for Playback {
+    fn actions(&mut self, _game: &Game) -> Vec<Action> {
+        let mut due = self.next;
+        if self.is_paused {
+            due = (self.next + self.steps).min(self.replay.inputs.len());
+            self.steps = 0;
+        } else {
+            self.time += get_frame_time() as f64 * self.speed;
+            while self
+                .replay
+                .inputs
+                .get(due)
+                .is_some_and(|(time, _)| *time <= self.time)
+            {
+                due += 1;
+            }
+        }
+
+        let actions = self.replay.inputs[self.next..due]
+            .iter()
+            .map(|(_, dir)| Action::Move(*dir))
+            .collect();
+        if let Some((time, _)) = due.checked_sub(1).map(|i| self.replay.inputs[i]) {
+            self.time = self.time.max(time);
+        }
+        self.next = due;
+
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One move a second
+    fn replay_of(map: &HexMap, moves: &[HexDirection]) -> Replay {
+        Replay {
+            code: map.to_code().unwrap(),
+            seed: 0,
+            inputs: moves
+                .iter()
+                .enumerate()
+                .map(|(i, dir)| (i as f64, *dir))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn optimal_solutions_win() {
+        for map in premade_maps() {
+            let moves = map.solve().moves.unwrap();
+            let verification = replay_of(&map, &moves).verify().unwrap();
+
+            assert_eq!(
+                verification,
+                Verification {
+                    is_won: true,
+                    moves: moves.len(),
+                    first_bad: None,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn falling_off_loses() {
+        let map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        let verification = replay_of(&map, &[HexDirection::W]).verify().unwrap();
+
+        assert!(!verification.is_won);
+        assert_eq!(verification.moves, 1);
+        assert_eq!(
+            verification.first_bad,
+            Some((1, HexDirection::W, BadMove::Fell))
+        );
+    }
+
+    #[test]
+    fn moves_out_of_order_are_caught() {
+        let map = load_map(HexMap::PREMADE_MAP_1).unwrap();
+        let mut replay = replay_of(&map, &map.solve().moves.unwrap());
+        replay.inputs[2].0 = 0.5;
+
+        let verification = replay.verify().unwrap();
+        let dir = replay.inputs[2].1;
+        assert!(!verification.is_won);
+        assert_eq!(verification.moves, 2);
+        assert_eq!(verification.first_bad, Some((3, dir, BadMove::OutOfOrder)));
+    }
+
+    #[test]
+    fn moves_after_the_end_are_caught() {
+        let map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        let mut moves = map.solve().moves.unwrap();
+        let won_in = moves.len();
+        moves.push(HexDirection::E);
+
+        let verification = replay_of(&map, &moves).verify().unwrap();
+        assert!(!verification.is_won);
+        assert_eq!(verification.moves, won_in);
+        assert_eq!(
+            verification.first_bad,
+            Some((won_in + 1, HexDirection::E, BadMove::AfterEnd))
+        );
+
+        // A fall ends the attempt too
+        let moves = [HexDirection::W, HexDirection::E];
+        let verification = replay_of(&map, &moves).verify().unwrap();
+        assert_eq!(
+            verification.first_bad,
+            Some((1, HexDirection::W, BadMove::Fell))
+        );
+    }
+
+    #[test]
+    fn unfinished_attempts_neither_win_nor_fail() {
+        let map = load_map(HexMap::PREMADE_MAP_2).unwrap();
+        let moves = map.solve().moves.unwrap();
+
+        let verification = replay_of(&map, &moves[..3]).verify().unwrap();
+        assert_eq!(
+            verification,
+            Verification {
+                is_won: false,
+                moves: 3,
+                first_bad: None,
+            }
+        );
+    }
+
+    #[test]
+    fn bad_codes_dont_verify() {
+        let replay = Replay {
+            code: "not a code!".to_owned(),
+            seed: 0,
+            inputs: Vec::new(),
+        };
+        assert_eq!(replay.verify(), Err(CodeError::InvalidBase64));
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let map = load_map(HexMap::PREMADE_MAP_3).unwrap();
+        let mut replay = replay_of(&map, &map.solve().moves.unwrap());
+        replay.seed = 42;
+        replay.inputs[0].0 = 0.125;
+
+        assert_eq!(replay.to_string().parse::<Replay>().unwrap(), replay);
+    }
+
+    #[test]
+    fn replay_move_errors() {
+        let e = "code: x\n---\n0.5 E\n  1.0 Q\n"
+            .parse::<Replay>()
+            .unwrap_err();
+        assert_eq!((e.line, e.column), (4, 3));
+        assert!(matches!(e.kind, ParseErrorKind::InvalidMove(_)));
+    }
+}
@@ -0,0 +1,35 @@
+use macroquad::prelude::rand;
+
+// Small seeded generator (SplitMix64) for anything that has to come out the
+// same every run, like generated level packs. Each one keeps its own state,
+// so threads don't share one like they do `macroquad::rand`.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    // A seed from `macroquad::rand`, which the game seeds from the clock
+    pub fn random_seed() -> u64 {
+        ((rand::rand() as u64) << 32) | rand::rand() as u64
+    }
+
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // In `low..high`, like `macroquad::rand::gen_range`
+    pub fn gen_range(&mut self, low: usize, high: usize) -> usize {
+        assert!(low < high);
+        low + (self.next_u64() % (high - low) as u64) as usize
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::core::game::PlayerState;
+use crate::core::hex::*;
+use crate::core::map::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    // Moves of an optimal solution, `None` if the goal can't be reached
+    pub moves: Option<Vec<HexDirection>>,
+    // States taken off the queue before the search ended
+    pub expanded: usize,
+}
+
+impl HexMap {
+    // Breadth first search from the level's start that stops as soon as the
+    // block stands on the goal
+    pub fn solve(&self) -> Solution {
+        self.solve_from(PlayerState::Standing(self.start))
+    }
+
+    pub fn solve_from(&self, start: PlayerState) -> Solution {
+        let goal = PlayerState::Standing(self.goal);
+
+        // How each state was first reached
+        let mut parent: HashMap<PlayerState, Option<(PlayerState, HexDirection)>> =
+            HashMap::from([(start, None)]);
+        let mut queue = VecDeque::from([start]);
+        let mut expanded = 0;
+
+        while let Some(state) = queue.pop_front() {
+            expanded += 1;
+
+            if state == goal {
+                let mut moves = Vec::new();
+                let mut cur = state;
+                while let Some((prev, dir)) = parent[&cur] {
+                    moves.push(dir);
+                    cur = prev;
+                }
+                moves.reverse();
+
+                return Solution {
+                    moves: Some(moves),
+                    expanded,
+                };
+            }
+
+            for dir in HEX_DIRECTIONS {
+                let next = state.next_state_in_map(dir, self);
+                if next.is_dead() || parent.contains_key(&next) {
+                    continue;
+                }
+
+                parent.insert(next, Some((state, dir)));
+                queue.push_back(next);
+            }
+        }
+
+        Solution {
+            moves: None,
+            expanded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn premade_maps_solve_in_known_moves() {
+        let premade = [
+            (HexMap::PREMADE_MAP_0, 3),
+            (HexMap::PREMADE_MAP_1, 12),
+            (HexMap::PREMADE_MAP_2, 13),
+            (HexMap::PREMADE_MAP_3, 15),
+        ];
+
+        for (arr, expected) in premade {
+            let map = load_map(arr).unwrap();
+            let moves = map.solve().moves.unwrap();
+            assert_eq!(moves.len(), expected);
+
+            // The moves must actually get there
+            let mut state = PlayerState::Standing(map.start);
+            for dir in moves {
+                state = state.next_state_in_map(dir, &map);
+                assert!(!state.is_dead());
+            }
+            assert_eq!(state, PlayerState::Standing(map.goal));
+        }
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_moves() {
+        let mut map = load_map(HexMap::PREMADE_MAP_0).unwrap();
+        map.goal = Hex::from_axial(8, 8);
+        map.hexmap.insert(map.goal).unwrap();
+
+        let solution = map.solve();
+        assert_eq!(solution.moves, None);
+        assert!(solution.expanded > 0);
+    }
+}
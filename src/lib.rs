@@ -0,0 +1,3 @@
+pub mod core;
+
+pub const HEXES_SIZE: f32 = 32.0;
@@ -1,11 +1,15 @@
 use macroquad::audio::{play_sound, play_sound_once, set_sound_volume, PlaySoundParams};
 use macroquad::prelude::*;
 
-mod core;
-
-use core::game::*;
-
-const HEXES_SIZE: f32 = 32.0;
+use hexorz::core::editor::*;
+use hexorz::core::event::Event;
+use hexorz::core::game::*;
+use hexorz::core::input::*;
+use hexorz::core::level::Level;
+use hexorz::core::map::HexMap;
+use hexorz::core::notation::format_moves;
+use hexorz::core::pause::*;
+use hexorz::core::replay::*;
 
 const FRAGMENT_SHADER: &str = include_str!("starfield-shader.glsl");
 
@@ -32,6 +36,17 @@ async fn main() {
     let mut game_state = GameState::MainMenu;
     let mut game = Game::init().await;
     let mut is_debug = false;
+    let mut select_pack = 0;
+    let mut select_level = 0;
+    let mut toast: Option<(String, f64)> = None;
+    let mut editor: Option<Editor> = None;
+    let mut playback: Option<Playback> = None;
+    // Saved replays to choose from, newest first
+    let mut saved_replays: Vec<SavedReplay> = Vec::new();
+    let mut select_replay = 0;
+    let mut keyboard = KeyboardMouse;
+    let mut gamepad = Gamepad::new();
+    let mut pause_menu = PauseMenu::new();
 
     let direction_modifier: f32 = 0.0;
     let render_target = render_target(320, 150);
@@ -61,6 +76,9 @@ async fn main() {
 
     loop {
         clear_background(BLACK);
+        gamepad.poll();
+        let mut menu = keyboard.menu_controls();
+        menu.extend(gamepad.menu_controls());
 
         material.set_uniform("iResolution", (screen_width(), screen_height()));
         material.set_uniform("direction_modifier", direction_modifier);
@@ -84,6 +102,54 @@ async fn main() {
                     game.update_level(false);
                     game_state = GameState::Playing;
                 }
+                if is_key_pressed(KeyCode::P) {
+                    // On wasm this is whatever was last pasted into the page
+                    let code = miniquad::window::clipboard_get().unwrap_or_default();
+                    match HexMap::from_code(&code) {
+                        Ok(game_map) => {
+                            game.play_map(game_map);
+                            game_state = GameState::Playing;
+                        }
+                        Err(e) => {
+                            toast = Some((e.to_string(), get_time()));
+                        }
+                    }
+                }
+                if is_key_pressed(KeyCode::E) {
+                    let editor = editor.get_or_insert_with(|| {
+                        let level = match game.current_level() {
+                            Some(Ok(level)) if level.map == game.map => level.clone(),
+                            _ => Level::from_map(game.map.clone()),
+                        };
+                        Editor::new(level)
+                    });
+                    editor.enter(&mut game);
+                    game_state = GameState::Editor;
+                }
+                if is_key_pressed(KeyCode::R) {
+                    saved_replays = Replay::saved()
+                        .into_iter()
+                        .map(|path| {
+                            // Checked once here, not every frame the list is up
+                            let replay = Replay::load(&path).and_then(|replay| {
+                                let verification =
+                                    replay.verify().map_err(|e| format!("{}: {}", path, e))?;
+                                Ok((replay, verification))
+                            });
+                            (path, replay)
+                        })
+                        .collect();
+                    select_replay = 0;
+                    game_state = GameState::ReplaySelect;
+                }
+                if is_key_pressed(KeyCode::V) {
+                    game.cycle_lives(1);
+                }
+                if is_key_pressed(KeyCode::L) {
+                    select_pack = game.pack_index.min(game.packs.len() - 1);
+                    select_level = 0;
+                    game_state = GameState::LevelSelect;
+                }
 
                 let text = "Press SPACE to start";
                 let text_dimensions = measure_text(text, None, 50, 1.0);
@@ -94,42 +160,335 @@ async fn main() {
                     50.0,
                     RED,
                 );
+
+                let text = "Press L to select a level";
+                let text_dimensions = measure_text(text, None, 30, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 50.0,
+                    30.0,
+                    GRAY,
+                );
+
+                let text = "Press P to play a level code from the clipboard";
+                let text_dimensions = measure_text(text, None, 30, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 85.0,
+                    30.0,
+                    GRAY,
+                );
+
+                let text = "Press E to open the level editor";
+                let text_dimensions = measure_text(text, None, 30, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 120.0,
+                    30.0,
+                    GRAY,
+                );
+
+                let text = "Press R to watch a replay";
+                let text_dimensions = measure_text(text, None, 30, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 155.0,
+                    30.0,
+                    GRAY,
+                );
+
+                let lives = match game.max_lives {
+                    Some(lives) => lives.to_string(),
+                    None => "unlimited".to_owned(),
+                };
+                let text = format!("Press V to change lives: {}", lives);
+                let text_dimensions = measure_text(&text, None, 30, 1.0);
+                draw_text(
+                    &text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 190.0,
+                    30.0,
+                    GRAY,
+                );
             }
-            GameState::Playing => {
-                set_sound_volume(&game.theme_music, 0.8);
+            GameState::LevelSelect => {
+                set_default_camera();
+                let level_total = game.packs[select_pack].levels.len();
 
+                if is_key_pressed(KeyCode::Left) && select_pack > 0 {
+                    select_pack -= 1;
+                    select_level = 0;
+                }
+                if is_key_pressed(KeyCode::Right) && select_pack + 1 < game.packs.len() {
+                    select_pack += 1;
+                    select_level = 0;
+                }
+                if is_key_pressed(KeyCode::Up) && select_level > 0 {
+                    select_level -= 1;
+                }
+                if is_key_pressed(KeyCode::Down) && select_level + 1 < level_total {
+                    select_level += 1;
+                }
+                if is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::Backspace) {
+                    game_state = GameState::MainMenu;
+                }
+                if (is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::Enter))
+                    && level_total > 0
+                {
+                    game.select_level(select_pack, select_level);
+                    game_state = GameState::Playing;
+                }
+
+                draw_level_select(&game, select_pack, select_level);
+            }
+            GameState::Playing if game.level_error.is_some() => {
+                game_state = GameState::LevelError;
+            }
+            GameState::Playing
+                if is_key_pressed(KeyCode::Tab)
+                    && editor.as_ref().is_some_and(|e| e.is_playtest) =>
+            {
+                if let Some(editor) = editor.as_mut() {
+                    editor.enter(&mut game);
+                }
+                game_state = GameState::Editor;
+            }
+            GameState::Playing if menu.contains(&MenuControl::Back) => {
+                game.pause();
+                pause_menu = PauseMenu::new();
+                game_state = GameState::Paused;
+            }
+            GameState::Playing => {
                 if is_key_pressed(KeyCode::Y) {
                     is_debug = !is_debug;
                 }
+                if is_key_pressed(KeyCode::C) {
+                    let text = match game.map.to_code() {
+                        Ok(code) => {
+                            miniquad::window::clipboard_set(&code);
+                            "Level code copied".to_owned()
+                        }
+                        Err(e) => e.to_string(),
+                    };
+                    toast = Some((text, get_time()));
+                }
 
-                game.update();
+                game.update(&mut keyboard);
+                game.update(&mut gamepad);
 
-                if let PlayerState::Dead = game.player_state {
-                    game_state = GameState::GameOver;
-                    set_sound_volume(&game.theme_music, 0.4);
+                for event in game.drain_events() {
+                    play_event_sound(&game, &event);
 
-                    play_sound_once(&game.sound_explosion);
+                    match event {
+                        Event::Fell { .. } => {
+                            game.lose_life();
+                            game_state = GameState::GameOver;
+                        }
+                        Event::Won => {
+                            game.complete_level();
+                            game_state = GameState::GameWon;
+                        }
+                        _ => {}
+                    }
                 }
 
-                if game.player_state == PlayerState::Standing(game.map.goal) {
-                    game_state = GameState::GameWon;
-                    set_sound_volume(&game.theme_music, 0.4);
+                if matches!(game_state, GameState::GameOver | GameState::GameWon) {
+                    game.end_attempt();
                 }
 
                 game.draw(is_debug);
+                game.draw_hover();
 
                 set_default_camera();
 
-                let text = format!("Level: {}", game.level_count);
+                let text = if game.is_custom {
+                    "Custom level".to_owned()
+                } else {
+                    format!("Level: {}", game.level_count)
+                };
                 let text_dimensions = measure_text(&text, None, 50, 1.0);
 
                 draw_text(&text, 10.0, 10.0 + text_dimensions.height, 50.0, GREEN);
+
+                if game.max_lives.is_some() && !game.is_custom {
+                    let text = format!("Lives: {}", game.lives);
+                    draw_text(&text, 10.0, 90.0, 30.0, GREEN);
+                }
+
+                if is_debug {
+                    if let Some(moves) = game.map.solve_from(game.player_state).moves {
+                        let hint = format!("Hint: {}", format_moves(&moves));
+                        draw_text(&hint, 10.0, 125.0, 30.0, YELLOW);
+                    }
+                }
+            }
+            GameState::Paused => {
+                game.draw(false);
+                pause_menu.draw(&game);
+
+                match pause_menu.update(&mut game, &menu) {
+                    Some(PauseItem::Resume) => {
+                        game.resume();
+                        game_state = GameState::Playing;
+                    }
+                    Some(PauseItem::Restart) => {
+                        game.restart();
+                        game_state = GameState::Playing;
+                    }
+                    Some(PauseItem::Undo) => {
+                        game.undo();
+                        game.resume();
+                        game_state = GameState::Playing;
+                    }
+                    Some(PauseItem::LevelSelect) => {
+                        game.end_attempt();
+                        // Whatever is picked there isn't a playtest
+                        if let Some(editor) = editor.as_mut() {
+                            editor.is_playtest = false;
+                        }
+                        select_pack = game.pack_index.min(game.packs.len() - 1);
+                        select_level = 0;
+                        game_state = GameState::LevelSelect;
+                    }
+                    Some(PauseItem::Quit) => {
+                        game.end_attempt();
+                        match editor.as_mut().filter(|e| e.is_playtest) {
+                            Some(editor) => {
+                                editor.enter(&mut game);
+                                game_state = GameState::Editor;
+                            }
+                            None => game_state = GameState::MainMenu,
+                        }
+                    }
+                    Some(PauseItem::Settings) | None => {}
+                }
+            }
+            GameState::Editor => {
+                let editor =
+                    editor.get_or_insert_with(|| Editor::new(Level::from_map(game.map.clone())));
+
+                editor.update(&mut game);
+
+                if is_key_pressed(KeyCode::S) {
+                    toast = Some((editor.save(), get_time()));
+                }
+                if is_key_pressed(KeyCode::Tab) {
+                    if editor.status.is_ok() {
+                        game.play_map(editor.level.map.clone());
+                        editor.is_playtest = true;
+                        game_state = GameState::Playing;
+                    } else {
+                        toast = Some(("Fix the map before playtesting".to_owned(), get_time()));
+                    }
+                }
+                if is_key_pressed(KeyCode::Escape) {
+                    editor.leave(&mut game);
+                    game_state = GameState::MainMenu;
+                }
+
+                game.draw(false);
+                editor.draw(&game);
+            }
+            GameState::ReplaySelect => {
+                set_default_camera();
+
+                if is_key_pressed(KeyCode::Up) && select_replay > 0 {
+                    select_replay -= 1;
+                }
+                if is_key_pressed(KeyCode::Down) && select_replay + 1 < saved_replays.len() {
+                    select_replay += 1;
+                }
+                if is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::Backspace) {
+                    saved_replays.clear();
+                    game_state = GameState::MainMenu;
+                }
+                if is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::Enter) {
+                    if let Some((_, Ok((replay, _)))) = saved_replays.get(select_replay) {
+                        let mut loaded = Playback::new(replay.clone());
+                        match loaded.start(&mut game) {
+                            Ok(()) => {
+                                playback = Some(loaded);
+                                game_state = GameState::Replay;
+                            }
+                            Err(e) => toast = Some((e.to_string(), get_time())),
+                        }
+                    }
+                }
+
+                draw_replay_select(&saved_replays, select_replay);
+            }
+            GameState::Replay => {
+                if let Some(playback) = playback.as_mut() {
+                    playback.update(&mut keyboard, &mut game);
+                    playback.update(&mut gamepad, &mut game);
+                    game.update(playback);
+                    game.draw(false);
+                    playback.draw();
+                }
+
+                if is_key_pressed(KeyCode::Escape) {
+                    playback = None;
+                    game_state = GameState::ReplaySelect;
+                }
+            }
+            GameState::LevelError => {
+                set_default_camera();
+                if is_key_pressed(KeyCode::Space) || is_key_pressed(KeyCode::Escape) {
+                    game_state = GameState::LevelSelect;
+                }
+
+                let text = "Level failed to load";
+                let text_dimensions = measure_text(text, None, 50, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0,
+                    50.0,
+                    RED,
+                );
+
+                let text = game.level_error.as_deref().unwrap_or_default();
+                let text_dimensions = measure_text(text, None, 24, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 40.0,
+                    24.0,
+                    WHITE,
+                );
             }
             GameState::GameOver => {
+                let is_out_of_lives = game.is_out_of_lives();
+
+                // Same map and seed again, as long as the run has lives left
+                if is_key_pressed(KeyCode::R) && !is_out_of_lives {
+                    game.restart();
+                    game_state = GameState::Playing;
+                }
                 if is_key_pressed(KeyCode::Space) {
-                    game_state = GameState::MainMenu;
-                    game.level_count = 0;
+                    match editor.as_mut().filter(|e| e.is_playtest) {
+                        Some(editor) => {
+                            editor.enter(&mut game);
+                            game_state = GameState::Editor;
+                        }
+                        None => {
+                            // Without lives the run goes on from this level
+                            // next time
+                            if is_out_of_lives {
+                                game.reset_run();
+                            }
+                            game_state = GameState::MainMenu;
+                        }
+                    }
                 }
+                // The board stays up with the fatal hexes marked
+                game.draw(false);
+                set_default_camera();
+
                 let text = "GAME OVER!";
                 let text_dimensions = measure_text(text, None, 50, 1.0);
                 draw_text(
@@ -139,16 +498,54 @@ async fn main() {
                     50.0,
                     RED,
                 );
+
+                if let PlayerState::Dead(fall) = game.player_state {
+                    let text = fall.to_string();
+                    let text_dimensions = measure_text(&text, None, 30, 1.0);
+                    draw_text(
+                        &text,
+                        screen_width() / 2.0 - text_dimensions.width / 2.0,
+                        screen_height() / 2.0 + 40.0,
+                        30.0,
+                        WHITE,
+                    );
+                }
+
+                let text = if is_out_of_lives {
+                    "Out of lives, press SPACE to start over".to_owned()
+                } else if game.max_lives.is_some() && !game.is_custom {
+                    format!(
+                        "Press R to retry ({} lives left), SPACE for the menu",
+                        game.lives
+                    )
+                } else {
+                    "Press R to retry, SPACE for the menu".to_owned()
+                };
+                let text_dimensions = measure_text(&text, None, 30, 1.0);
+                draw_text(
+                    &text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() / 2.0 + 80.0,
+                    30.0,
+                    GRAY,
+                );
             }
             GameState::GameWon => {
                 if is_debug {
                     println!("LV: {}", game.level_count);
-                    game.map.dump_map(9);
+                    print!("{}", game.map.to_ascii(None));
                 }
 
                 if is_key_pressed(KeyCode::Space) {
-                    game.update_level(true);
-                    game_state = GameState::Playing;
+                    if let Some(editor) = editor.as_mut().filter(|e| e.is_playtest) {
+                        editor.enter(&mut game);
+                        game_state = GameState::Editor;
+                    } else if game.is_custom {
+                        game_state = GameState::MainMenu;
+                    } else {
+                        game.update_level(true);
+                        game_state = GameState::Playing;
+                    }
                 }
 
                 let text = format!("Level {} passed", game.level_count);
@@ -164,10 +561,192 @@ async fn main() {
             }
         }
 
+        if let Some(e) = game.save_error.take() {
+            toast = Some((e, get_time()));
+        }
+
+        // Whatever the state above didn't handle itself
+        for event in game.drain_events() {
+            play_event_sound(&game, &event);
+        }
+
+        if let Some((text, time)) = &toast {
+            if get_time() - time > 2.0 {
+                toast = None;
+            } else {
+                set_default_camera();
+                let text_dimensions = measure_text(text, None, 30, 1.0);
+                draw_text(
+                    text,
+                    screen_width() / 2.0 - text_dimensions.width / 2.0,
+                    screen_height() - 40.0,
+                    30.0,
+                    YELLOW,
+                );
+            }
+        }
+
         next_frame().await
     }
 }
 
+fn play_event_sound(game: &Game, event: &Event) {
+    match event {
+        Event::Fell { .. } => {
+            set_sound_volume(&game.theme_music, 0.4);
+            play_sound_once(&game.sound_explosion);
+        }
+        Event::Won => set_sound_volume(&game.theme_music, 0.4),
+        // Normal volume again once the block is back in play
+        Event::LevelLoaded | Event::Undone { .. } => set_sound_volume(&game.theme_music, 0.8),
+        _ => {}
+    }
+}
+
+fn draw_level_select(game: &Game, pack_index: usize, level_index: usize) {
+    let pack = &game.packs[pack_index];
+
+    let title = format!(
+        "< {} >  ({}/{})",
+        pack.name,
+        pack_index + 1,
+        game.packs.len()
+    );
+    draw_text(&title, 40.0, 60.0, 50.0, GREEN);
+    if !pack.author.is_empty() {
+        let text = format!("by {}", pack.author);
+        let text_dimensions = measure_text(&title, None, 50, 1.0);
+        draw_text(&text, 60.0 + text_dimensions.width, 60.0, 30.0, GRAY);
+    }
+
+    // Scroll so the selected level is always on screen
+    let line_height = 36.0;
+    let errors_height = game.pack_errors.len() as f32 * 28.0;
+    let rows = (((screen_height() - 140.0 - errors_height) / line_height) as usize).max(1);
+    let first = (level_index + 1).saturating_sub(rows);
+
+    for (i, level) in pack.levels.iter().enumerate().skip(first).take(rows) {
+        let marker = if game.is_completed(pack_index, i) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let text = match level {
+            Ok(level) => {
+                let name = if level.name.is_empty() {
+                    format!("Level {}", i + 1)
+                } else {
+                    level.name.clone()
+                };
+                let mut text = format!("{} {:>2}. {}", marker, i + 1, name);
+                if let Some(par) = level.par {
+                    text += &format!("  (par {})", par);
+                }
+                if let Some(difficulty) = pack.difficulty[i] {
+                    text += &format!("  {}", difficulty.label());
+                }
+                text
+            }
+            Err(error) => format!(" !  {:>2}. {}", i + 1, error),
+        };
+        let color = match (i == level_index, level.is_ok()) {
+            (true, _) => YELLOW,
+            (false, true) => WHITE,
+            (false, false) => RED,
+        };
+
+        draw_text(
+            &text,
+            40.0,
+            120.0 + (i - first) as f32 * line_height,
+            30.0,
+            color,
+        );
+    }
+
+    for (i, error) in game.pack_errors.iter().enumerate() {
+        let y = screen_height() - 50.0 - errors_height + (i + 1) as f32 * 28.0;
+        draw_text(error, 40.0, y, 24.0, RED);
+    }
+
+    draw_text(
+        "LEFT/RIGHT pack, UP/DOWN level, SPACE play, ESC back",
+        40.0,
+        screen_height() - 20.0,
+        24.0,
+        GRAY,
+    );
+}
+
+// A replay file's path and what it holds, or why it can't be watched
+type SavedReplay = (String, Result<(Replay, Verification), String>);
+
+fn draw_replay_select(replays: &[SavedReplay], selected: usize) {
+    draw_text("Replays", 40.0, 60.0, 50.0, GREEN);
+
+    if replays.is_empty() {
+        draw_text(
+            "No replays yet, they're saved after every attempt",
+            40.0,
+            120.0,
+            30.0,
+            GRAY,
+        );
+    }
+
+    // Scroll so the selected replay is always on screen
+    let line_height = 36.0;
+    let rows = (((screen_height() - 140.0) / line_height) as usize).max(1);
+    let first = (selected + 1).saturating_sub(rows);
+
+    for (i, (path, replay)) in replays.iter().enumerate().skip(first).take(rows) {
+        let name = path
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(path)
+            .trim_end_matches(".hexr");
+        let text = match replay {
+            Ok((replay, verification)) => {
+                let result = match verification.first_bad {
+                    _ if verification.is_won => "won",
+                    Some((_, _, BadMove::Fell)) => "lost",
+                    Some(_) => "illegal",
+                    None => "unfinished",
+                };
+                format!(
+                    "{:>3}. {}  ({} moves, {})",
+                    i + 1,
+                    name,
+                    replay.inputs.len(),
+                    result
+                )
+            }
+            Err(error) => format!("{:>3}. {}", i + 1, error),
+        };
+        let color = match (i == selected, replay.is_ok()) {
+            (true, _) => YELLOW,
+            (false, true) => WHITE,
+            (false, false) => RED,
+        };
+
+        draw_text(
+            &text,
+            40.0,
+            120.0 + (i - first) as f32 * line_height,
+            30.0,
+            color,
+        );
+    }
+
+    draw_text(
+        "UP/DOWN replay, SPACE watch, ESC back",
+        40.0,
+        screen_height() - 20.0,
+        24.0,
+        GRAY,
+    );
+}
+
 fn window_conf() -> Conf {
     Conf {
         window_title: "Hexorz".to_owned(),